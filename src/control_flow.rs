@@ -0,0 +1,243 @@
+use crate::hazards::*;
+use crate::isa::*;
+use crate::parser::*;
+use std::ops::Range;
+
+// Splits a program into basic blocks, runs of instructions that always run
+// one after another, joined by the ways the program can go from one to the
+// next.
+//
+// A branch takes effect after its delay slots, so its block ends after the
+// last of them. Running off the end of the program runs the unused lines of
+// ROM, which are NOOPs, until the program counter wraps back to 0. Jumping
+// past the end of the program does the same.
+//
+// The CFG is used to warn about lines that can never run, and loops that the
+// program can never leave that never OUT, so nothing is shown once they start.
+// Loops that only jump, like `done: JMP done`, are taken to be the program
+// stopping on purpose.
+
+/// How the program goes from one block to another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    /// Runs on to the next line
+    Next,
+    /// A JMP
+    Jump,
+    /// A BIE when the flags are equal
+    Taken,
+    /// Runs past the end of the program and wraps back to 0
+    Wrap,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    /// The block the program goes to
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    /// The addresses of the instructions in the block
+    pub addresses: Range<usize>,
+    pub edges: Vec<Edge>,
+}
+
+/// A program's control-flow graph. Block 0 is where the program starts.
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(instructions: &[Instruction], pipeline: &Pipeline) -> Self {
+        let slots = pipeline.delay_slots();
+        // Where each block starts
+        let mut starts = vec![false; instructions.len()];
+        if let Some(first) = starts.first_mut() {
+            *first = true;
+        }
+        for (address, instruction) in instructions.iter().enumerate() {
+            if !is_branch(instruction) {
+                continue;
+            }
+            if let Some(target) = starts.get_mut(instruction.a.data() as usize) {
+                *target = true;
+            }
+            if let Some(after) = starts.get_mut(address + 1 + slots) {
+                *after = true;
+            }
+        }
+        let mut blocks: Vec<BasicBlock> = vec![];
+        for (address, start) in starts.iter().enumerate() {
+            match blocks.last_mut() {
+                Some(block) if !start => block.addresses.end += 1,
+                _ => blocks.push(BasicBlock {
+                    addresses: address..address + 1,
+                    edges: vec![],
+                }),
+            }
+        }
+        // Which block the program is in after going to an address
+        let block_at = |address: usize| match blocks
+            .iter()
+            .position(|block| block.addresses.contains(&address))
+        {
+            Some(block) => Edge {
+                to: block,
+                kind: EdgeKind::Next,
+            },
+            None => Edge {
+                to: 0,
+                kind: EdgeKind::Wrap,
+            },
+        };
+        let edges: Vec<Vec<Edge>> = blocks
+            .iter()
+            .map(|block| {
+                let end = block.addresses.end;
+                let next = block_at(end);
+                // The branch that takes effect at the end of the block
+                let branch = (end.saturating_sub(slots + 1)..end).rev().find(|branch| {
+                    is_branch(&instructions[*branch])
+                        && shadow(instructions, *branch, pipeline).end == end
+                });
+                let Some(branch) = branch else {
+                    return vec![next];
+                };
+                let instruction = &instructions[branch];
+                let mut target = block_at(instruction.a.data() as usize);
+                if target.kind == EdgeKind::Next {
+                    target.kind = match instruction.operation.definition().branch {
+                        Branch::Always => EdgeKind::Jump,
+                        _ => EdgeKind::Taken,
+                    };
+                }
+                // Blocks that are jumped into part way through a branch's
+                // delay slots can also be run without the branch
+                let falls_through = instruction.operation.definition().branch != Branch::Always
+                    || branch < block.addresses.start;
+                match falls_through {
+                    true => vec![target, next],
+                    false => vec![target],
+                }
+            })
+            .collect();
+        for (block, edges) in blocks.iter_mut().zip(edges) {
+            block.edges = edges;
+        }
+        ControlFlowGraph { blocks }
+    }
+
+    /// The block an address is in.
+    pub fn block_of(&self, address: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| block.addresses.contains(&address))
+    }
+
+    // Which blocks can be got to from `start`, going round by wrapping only if
+    // `follow_wraps` is set
+    fn reached_from(&self, start: usize, follow_wraps: bool) -> Vec<bool> {
+        let mut reached = vec![false; self.blocks.len()];
+        let mut to_visit = vec![start];
+        while let Some(block) = to_visit.pop() {
+            if block >= self.blocks.len() || reached[block] {
+                continue;
+            }
+            reached[block] = true;
+            let edges = self.blocks[block].edges.iter();
+            to_visit.extend(
+                edges
+                    .filter(|edge| follow_wraps || edge.kind != EdgeKind::Wrap)
+                    .map(|edge| edge.to),
+            );
+        }
+        reached
+    }
+
+    /// Whether each block can be run, starting from block 0.
+    pub fn reachable(&self) -> Vec<bool> {
+        self.reached_from(0, true)
+    }
+
+    /// The loops the program can't leave once it gets to them, each as the
+    /// blocks in it in order. Going round by wrapping past the end of the
+    /// program doesn't count as a loop.
+    pub fn endless_loops(&self) -> Vec<Vec<usize>> {
+        // Blocks are in the same loop when each can be got to from the other
+        let reached: Vec<Vec<bool>> = (0..self.blocks.len())
+            .map(|block| {
+                // A block only reaches itself by going round a loop
+                let mut reached = vec![false; self.blocks.len()];
+                for edge in &self.blocks[block].edges {
+                    if edge.kind != EdgeKind::Wrap {
+                        let from_edge = self.reached_from(edge.to, false);
+                        reached.iter_mut().zip(from_edge).for_each(|(r, e)| *r |= e);
+                    }
+                }
+                reached
+            })
+            .collect();
+        let mut loops: Vec<Vec<usize>> = vec![];
+        for (block, reached_from_block) in reached.iter().enumerate() {
+            if !reached_from_block[block] || loops.iter().any(|found| found.contains(&block)) {
+                continue;
+            }
+            let blocks: Vec<usize> = (0..self.blocks.len())
+                .filter(|other| reached_from_block[*other] && reached[*other][block])
+                .collect();
+            let leaves = blocks.iter().any(|block| {
+                self.blocks[*block]
+                    .edges
+                    .iter()
+                    .any(|edge| !blocks.contains(&edge.to))
+            });
+            if !leaves {
+                loops.push(blocks);
+            }
+        }
+        loops
+    }
+
+    /// The graph in Graphviz's DOT language, with each block showing its
+    /// instructions. Blocks that can't be run are grey.
+    pub fn to_dot(&self, instructions: &[Instruction]) -> String {
+        let reachable = self.reachable();
+        let mut dot = String::from("digraph program {\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for (block, basic_block) in self.blocks.iter().enumerate() {
+            let label: String = basic_block
+                .addresses
+                .clone()
+                .map(|address| format!("{address:02}  {}\\l", instructions[address]))
+                .collect();
+            let style = match reachable[block] {
+                true => "",
+                false => ", color=grey, fontcolor=grey",
+            };
+            dot.push_str(&format!("    b{block} [label=\"{label}\"{style}];\n"));
+        }
+        for (block, basic_block) in self.blocks.iter().enumerate() {
+            for edge in &basic_block.edges {
+                let attributes = match edge.kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Taken => " [label=\"taken\"]",
+                    EdgeKind::Wrap => " [label=\"wrap\", style=dashed]",
+                };
+                dot.push_str(&format!("    b{block} -> b{}{attributes};\n", edge.to));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl Program {
+    /// The program's control-flow graph, as the emulator runs it.
+    pub fn control_flow_graph(&self) -> ControlFlowGraph {
+        ControlFlowGraph::new(&self.instructions, &Pipeline::EMULATOR)
+    }
+}
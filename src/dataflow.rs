@@ -0,0 +1,157 @@
+use crate::control_flow::*;
+use crate::hazards::*;
+use crate::parser::*;
+
+// Follows registers along every path through the control-flow graph.
+//
+// Registers start at 0 in the emulator, but the real computer doesn't clear
+// them, so a program that reads one before writing it may not work in
+// minecraft. Going forwards from the start, a register might not have been
+// written yet if there's any path to the reader that doesn't write it.
+//
+// Going backwards, a value is still wanted if there's any path from it to an
+// instruction that reads it before it's written again. Writes of values that
+// aren't wanted are wasted lines, and often a sign of the wrong register.
+//
+// Only blocks the program can get to are looked at.
+
+/// Something wrong with how a program uses its registers. Instructions are
+/// referred to by their address.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegisterIssue {
+    /// `reader` may read `register` before anything has written it.
+    Uninitialized { reader: usize, register: u8 },
+    /// Nothing reads the value `writer` writes to `register`.
+    DeadWrite { writer: usize, register: u8 },
+}
+
+// A set of registers, one bit each. Machines have at most 128 registers.
+type RegisterSet = u128;
+
+fn register_set(registers: impl IntoIterator<Item = u8>) -> RegisterSet {
+    registers
+        .into_iter()
+        .filter(|register| *register < RegisterSet::BITS as u8)
+        .fold(0, |set, register| set | 1 << register)
+}
+
+fn reads(instruction: &Instruction) -> RegisterSet {
+    register_set(read_registers(instruction))
+}
+
+fn writes(instruction: &Instruction) -> RegisterSet {
+    register_set(written_register(instruction))
+}
+
+fn registers_in(set: RegisterSet) -> impl Iterator<Item = u8> {
+    (0..RegisterSet::BITS as u8).filter(move |register| set & 1 << register != 0)
+}
+
+// The registers that might not have been written when each block starts
+fn unwritten_at_start(
+    instructions: &[Instruction],
+    graph: &ControlFlowGraph,
+    reachable: &[bool],
+) -> Vec<RegisterSet> {
+    let mut unwritten = vec![0; graph.blocks.len()];
+    if let Some(start) = unwritten.first_mut() {
+        // R0 always reads as 0
+        *start = register_set(1..RegisterSet::BITS as u8);
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (block, basic_block) in graph.blocks.iter().enumerate() {
+            if !reachable[block] {
+                continue;
+            }
+            let written = basic_block
+                .addresses
+                .clone()
+                .fold(0, |set, address| set | writes(&instructions[address]));
+            let at_end = unwritten[block] & !written;
+            for edge in &basic_block.edges {
+                if unwritten[edge.to] | at_end != unwritten[edge.to] {
+                    unwritten[edge.to] |= at_end;
+                    changed = true;
+                }
+            }
+        }
+    }
+    unwritten
+}
+
+// The registers whose values are still wanted when each block ends
+fn wanted_at_end(
+    instructions: &[Instruction],
+    graph: &ControlFlowGraph,
+    reachable: &[bool],
+) -> Vec<RegisterSet> {
+    let wanted_at_start = |block: usize, at_end: RegisterSet| {
+        graph.blocks[block]
+            .addresses
+            .clone()
+            .rev()
+            .fold(at_end, |wanted, address| {
+                let instruction = &instructions[address];
+                wanted & !writes(instruction) | reads(instruction)
+            })
+    };
+    let mut wanted = vec![0; graph.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (block, basic_block) in graph.blocks.iter().enumerate().rev() {
+            if !reachable[block] {
+                continue;
+            }
+            let at_end = basic_block.edges.iter().fold(0, |set, edge| {
+                set | wanted_at_start(edge.to, wanted[edge.to])
+            });
+            if at_end != wanted[block] {
+                wanted[block] = at_end;
+                changed = true;
+            }
+        }
+    }
+    wanted
+}
+
+/// Finds registers that may be read before they are written, and writes that
+/// nothing reads, on any path through the program.
+pub fn find_register_issues(
+    instructions: &[Instruction],
+    pipeline: &Pipeline,
+) -> Vec<RegisterIssue> {
+    let graph = ControlFlowGraph::new(instructions, pipeline);
+    let reachable = graph.reachable();
+    let unwritten = unwritten_at_start(instructions, &graph, &reachable);
+    let wanted = wanted_at_end(instructions, &graph, &reachable);
+    let mut issues = vec![];
+    for (block, basic_block) in graph.blocks.iter().enumerate() {
+        if !reachable[block] {
+            continue;
+        }
+        let mut still_unwritten = unwritten[block];
+        for reader in basic_block.addresses.clone() {
+            let instruction = &instructions[reader];
+            for register in registers_in(reads(instruction) & still_unwritten) {
+                issues.push(RegisterIssue::Uninitialized { reader, register });
+            }
+            still_unwritten &= !writes(instruction);
+        }
+        let mut still_wanted = wanted[block];
+        for writer in basic_block.addresses.clone().rev() {
+            let instruction = &instructions[writer];
+            for register in registers_in(writes(instruction) & !still_wanted) {
+                issues.push(RegisterIssue::DeadWrite { writer, register });
+            }
+            still_wanted = still_wanted & !writes(instruction) | reads(instruction);
+        }
+    }
+    issues.sort_by_key(|issue| match issue {
+        RegisterIssue::Uninitialized { reader, .. } => *reader,
+        RegisterIssue::DeadWrite { writer, .. } => *writer,
+    });
+    issues
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+/// An error or warning found while assembling a program. Lines and columns
+/// are counted from zero, and `length` is the number of characters of the
+/// offending token. Notes point at other lines involved, like the call of the
+/// macro an error is in. `file` is set when the line is in an included file
+/// rather than the one being assembled. `line_text` is the text of the line,
+/// so the diagnostic can be shown without reading the file again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub message: String,
+    pub help: Option<String>,
+    pub notes: Vec<Diagnostic>,
+    pub file: Option<String>,
+    pub line_text: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            line,
+            column,
+            length: 1,
+            message: message.into(),
+            help: None,
+            notes: vec![],
+            file: None,
+            line_text: None,
+        }
+    }
+
+    pub fn warning(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(line, column, message)
+        }
+    }
+
+    pub fn note(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Note,
+            ..Self::error(line, column, message)
+        }
+    }
+
+    pub fn with_length(mut self, length: usize) -> Self {
+        self.length = length.max(1);
+        self
+    }
+
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+
+    pub fn with_note(mut self, note: Diagnostic) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn with_file(mut self, file: Option<String>) -> Self {
+        self.file = file;
+        self
+    }
+
+    pub fn with_line_text(mut self, line_text: String) -> Self {
+        self.line_text = Some(line_text);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Formats the diagnostic like rustc does, with the offending line and a
+    /// caret under the token it refers to. `file_name` is the file being
+    /// assembled, shown for lines that aren't in an included file:
+    ///
+    /// ```text
+    /// error: "ADDD" is not a valid instruction
+    ///  --> example.elt:5:1
+    ///   |
+    /// 5 | ADDD R1 R2
+    ///   | ^^^^
+    ///   = help: did you mean ADD?
+    /// ```
+    pub fn render(&self, file_name: &str) -> String {
+        let file = self.file.as_deref().unwrap_or(file_name);
+        let line_number = (self.line + 1).to_string();
+        let gutter = " ".repeat(line_number.len());
+        let mut report = format!(
+            "{}: {}\n{gutter}--> {file}:{}:{}\n",
+            self.severity.name(),
+            self.message,
+            self.line + 1,
+            self.column + 1
+        );
+        if let Some(line) = &self.line_text {
+            // Keep tabs so the caret lines up with the source line. The column
+            // and length count bytes, so a character can be more than one.
+            let indent: String = line
+                .char_indices()
+                .take_while(|(index, _)| *index < self.column)
+                .map(|(_, char)| if char == '\t' { '\t' } else { ' ' })
+                .collect();
+            let carets = line
+                .get(self.column..self.column + self.length)
+                .map_or(self.length, |token| token.chars().count());
+            report += &format!("{gutter} |\n{line_number} | {line}\n");
+            report += &format!("{gutter} | {indent}{}\n", "^".repeat(carets));
+        }
+        if let Some(help) = &self.help {
+            report += &format!("{gutter} = help: {help}\n");
+        }
+        for note in &self.notes {
+            report += &note.render(file_name);
+        }
+        report
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+        }
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line + 1,
+            self.column + 1,
+            self.severity.name(),
+            self.message
+        )
+    }
+}
+
+// Levenshtein distance between two words
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Finds the candidate closest to `word`, if any is close enough to be a
+/// likely typo.
+pub fn closest_match<'a>(
+    word: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = (word.len() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
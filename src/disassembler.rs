@@ -0,0 +1,86 @@
+use crate::hazards::is_branch;
+use crate::parser::*;
+use std::collections::BTreeSet;
+
+// Turns ROM images written by `Program::to_rom` back into programs:
+//
+// L2:
+//     MOV R3 R1        ; 02: 20 03 01
+//     JMP L2           ; 03: 80 02 00
+//
+// Jump targets are given labels, and each line ends with its address and bytes.
+// Lines of NOOPs at the end of ROM are left out.
+
+// Where the comment with a line's address starts
+const COMMENT_COLUMN: usize = 20;
+
+// Jump targets are one byte, so no ROM can be longer than this
+const MAX_LINES: usize = 256;
+
+fn label(address: usize) -> String {
+    format!("L{address}")
+}
+
+/// Disassembles a ROM image into the source of a program. Images written by the
+/// assembler assemble back into the same bytes.
+pub fn disassemble(rom: &[u8]) -> Result<String, String> {
+    if !rom.len().is_multiple_of(Instruction::SIZE) {
+        return Err(format!(
+            "ROM images are made of {} byte lines, but this one is {} bytes long",
+            Instruction::SIZE,
+            rom.len()
+        ));
+    }
+    let lines = rom.len() / Instruction::SIZE;
+    if lines > MAX_LINES {
+        return Err(format!(
+            "ROM images can be at most {MAX_LINES} lines, but this one is {lines} lines long"
+        ));
+    }
+    let instructions = rom
+        .chunks_exact(Instruction::SIZE)
+        .enumerate()
+        .map(|(address, bytes)| {
+            let bytes = [bytes[0], bytes[1], bytes[2]];
+            Instruction::decode(bytes).map_err(|error| format!("Line {address}: {error}"))
+        })
+        .collect::<Result<Vec<Instruction>, String>>()?;
+    let targets: BTreeSet<usize> = instructions
+        .iter()
+        .filter(|instruction| is_branch(instruction))
+        .map(|instruction| instruction.a.data() as usize)
+        .filter(|target| *target < instructions.len())
+        .collect();
+    // Keep trailing NOOPs that are jumped to, so their label has a line
+    let length = instructions
+        .iter()
+        .rposition(|instruction| instruction.encode() != [0; Instruction::SIZE])
+        .map_or(0, |last| last + 1)
+        .max(targets.last().map_or(0, |target| target + 1))
+        .min(instructions.len());
+    let mut source = String::new();
+    for (address, instruction) in instructions.iter().take(length).enumerate() {
+        if targets.contains(&address) {
+            source += &format!("{}:\n", label(address));
+        }
+        let target = instruction.a.data() as usize;
+        let jumps_to_label = is_branch(instruction) && targets.contains(&target);
+        let code = match jumps_to_label {
+            true => format!(
+                "{} {}",
+                instruction.operation.definition().mnemonic,
+                label(target)
+            ),
+            false => instruction.to_string(),
+        };
+        let bytes = instruction
+            .encode()
+            .map(|byte| format!("{byte:02X}"))
+            .join(" ");
+        source += &format!(
+            "    {code:<width$} ; {address:02}: {bytes}\n",
+            width = COMMENT_COLUMN - 4
+        );
+    }
+    Ok(source)
+}
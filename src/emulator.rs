@@ -1,3 +1,5 @@
+use crate::isa::*;
+use crate::machine::Machine;
 use crate::parser::*;
 
 #[derive(Debug)]
@@ -16,7 +18,7 @@ pub struct AluFlags {
 
 #[derive(Debug)]
 pub struct Registers {
-    regs: [u8; 8],
+    regs: Vec<u8>,
 }
 
 impl Registers {
@@ -37,40 +39,30 @@ impl Registers {
 }
 
 impl Alu {
-    fn execute(&mut self, registers: &Registers, instruction: &Instruction) {
+    // Results are cut down to `max_word`, setting the overflow flag
+    fn execute(&mut self, registers: &Registers, instruction: &Instruction, max_word: u8) {
         let (a_data, b_data) = (
-            match instruction.operation_args {
-                OperationArgs::None => registers.read(instruction.a.data()),
-                OperationArgs::S => registers.read(instruction.a.data()),
-                OperationArgs::U => self.accumalator,
-                OperationArgs::X => self.accumalator,
+            match instruction.operation_args.uses_accumalator() {
+                true => self.accumalator,
+                false => registers.read(instruction.a.data()),
             } as u16,
             registers.read(instruction.b.data()) as u16,
         );
-        let mut result = match instruction.operation {
-            Operation::ADD => a_data + b_data,
-            Operation::ADDC => a_data + b_data + 1,
-            Operation::SHR => b_data >> 1,
-            Operation::NOT => !b_data,
-            _ => 0,
-        };
+        let result = instruction.operation.definition().alu.apply(a_data, b_data);
         self.flags = AluFlags {
             equals: a_data == b_data,
             greater_than: a_data > b_data,
             less_than: a_data < b_data,
-            over_flow: result > 255,
+            over_flow: result > max_word as u16,
         };
-        if result > 255 {
-            result -= 256;
-        }
-        self.accumalator = result as u8;
+        self.accumalator = (result & max_word as u16) as u8;
     }
 }
 
 #[derive(Debug)]
 pub struct Ports {
-    pub out: [u8; 8],
-    pub input: [u8; 8],
+    pub out: Vec<u8>,
+    pub input: Vec<u8>,
 }
 
 impl Ports {
@@ -100,9 +92,17 @@ pub struct Emulator {
 }
 
 impl Emulator {
-    const ROM_ADDRESS_BITS: u8 = 5;
+    /// An emulator of the machine the program was assembled for.
     pub fn new(program: Program) -> Self {
+        let machine = &program.machine;
         Self {
+            registers: Registers {
+                regs: vec![0; machine.registers],
+            },
+            ports: Ports {
+                out: vec![0; machine.output_ports],
+                input: vec![0; machine.input_ports],
+            },
             program,
             program_counter: 0,
             fetch_register: Instruction::none(),
@@ -118,35 +118,39 @@ impl Emulator {
                     over_flow: false,
                 },
             },
-            registers: Registers { regs: [0; 8] },
-            ports: Ports {
-                out: [0; 8],
-                input: [0; 8],
-            },
         }
     }
 
+    pub fn machine(&self) -> &Machine {
+        &self.program.machine
+    }
+
     fn increment_program_counter(&mut self) {
-        self.program_counter += 1;
-        if self.program_counter >= 2u8.pow(Self::ROM_ADDRESS_BITS as u32) {
+        self.program_counter = self.program_counter.wrapping_add(1);
+        if self.program_counter as usize >= self.program.machine.rom_lines {
             self.program_counter = 0
         }
     }
 
     fn check_for_branch(&mut self) {
-        match self.execute_register.operation {
-            Operation::JMP => self.program_counter = self.execute_register.a.data(),
-            Operation::BIE => self.program_counter = if self.alu.flags.equals {
-                self.execute_register.a.data()
-            } else {
-                self.program_counter
-            },
-            _ => (),
+        let taken = match self.execute_register.operation.definition().branch {
+            Branch::Never => false,
+            Branch::Always => true,
+            Branch::IfEqual => self.alu.flags.equals,
+        };
+        if taken {
+            self.program_counter = self.execute_register.a.data();
         }
     }
 
     fn fetch(&mut self) {
-        self.fetch_register = self.program.instructions[self.program_counter as usize].clone();
+        // Unused lines of ROM are NOOPs
+        self.fetch_register = self
+            .program
+            .instructions
+            .get(self.program_counter as usize)
+            .cloned()
+            .unwrap_or_else(Instruction::none);
     }
 
     fn decode(&mut self) {
@@ -157,7 +161,9 @@ impl Emulator {
         self.execute_register = self.decode_register.clone();
         //self.program_counter = self.execute_register.a.data();
         self.check_for_branch();
-        self.alu.execute(&self.registers, &self.execute_register);
+        let max_word = self.program.machine.max_word();
+        self.alu
+            .execute(&self.registers, &self.execute_register, max_word);
     }
 
     fn write_back(&mut self) {
@@ -166,19 +172,12 @@ impl Emulator {
             self.write_back_register.a.data(),
             self.write_back_register.b.data(),
         );
-        match self.write_back_register.operation {
-            Operation::NOOP => (),
-            Operation::IMM => self.registers.write(a, b),
-            Operation::MOV => self.registers.write(a, self.registers.read(b)),
-            Operation::ADD | Operation::ADDC => match self.write_back_register.operation_args {
-                OperationArgs::None => (),
-                OperationArgs::S => self.registers.write(a, self.alu.accumalator),
-                OperationArgs::U => self.registers.write(a, self.alu.accumalator),
-                OperationArgs::X => (),
-            },
-            Operation::SHR | Operation::NOT => self.registers.write(a, self.alu.accumalator),
-            Operation::OUT => self.ports.write_out(a, self.registers.read(b)),
-            Operation::JMP | Operation::BIE => (),
+        match self.write_back_register.write_back() {
+            WriteBack::Nothing => (),
+            WriteBack::Immediate => self.registers.write(a, b),
+            WriteBack::Copy => self.registers.write(a, self.registers.read(b)),
+            WriteBack::Result => self.registers.write(a, self.alu.accumalator),
+            WriteBack::Out => self.ports.write_out(a, self.registers.read(b)),
         }
     }
 
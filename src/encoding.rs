@@ -0,0 +1,119 @@
+use crate::isa::*;
+use crate::parser::*;
+
+// Instructions are stored in ROM as 3 bytes:
+//
+// byte 0: OOOO AA00   O = operation, A = ALU arguments, 0 = unused
+// byte 1: oprand A
+// byte 2: oprand B
+//
+// Operations are numbered by their opcode in `isa::INSTRUCTION_SET`, from
+// NOOP 0 to BIE 9, and ALU arguments none 0, S 1, U 2 and X 3. Only the
+// number in each oprand is stored, the operation decides whether it is a
+// register, port or immediate. Unused lines of ROM are all zeros, which is a
+// NOOP.
+
+impl Operation {
+    pub fn opcode(&self) -> u8 {
+        self.definition().opcode
+    }
+
+    pub fn from_opcode(opcode: u8) -> Option<Self> {
+        INSTRUCTION_SET
+            .iter()
+            .find(|definition| definition.opcode == opcode)
+            .map(|definition| definition.operation.clone())
+    }
+}
+
+impl OperationArgs {
+    fn bits(&self) -> u8 {
+        match self {
+            OperationArgs::None => 0,
+            OperationArgs::S => 1,
+            OperationArgs::U => 2,
+            OperationArgs::X => 3,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => OperationArgs::None,
+            1 => OperationArgs::S,
+            2 => OperationArgs::U,
+            _ => OperationArgs::X,
+        }
+    }
+}
+
+impl Oprand {
+    // An oprand of the same kind as `self` holding `data`
+    fn with_data(&self, data: u8) -> Self {
+        match self {
+            Oprand::Register(_) => Oprand::Register(data),
+            Oprand::MemoryAddress(_) => Oprand::MemoryAddress(data),
+            Oprand::Immediate(_) => Oprand::Immediate(data),
+            Oprand::Port(_) => Oprand::Port(data),
+        }
+    }
+}
+
+impl Instruction {
+    /// The number of bytes an instruction takes up in ROM.
+    pub const SIZE: usize = 3;
+
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        [
+            self.operation.opcode() << 4 | self.operation_args.bits() << 2,
+            self.a.data(),
+            self.b.data(),
+        ]
+    }
+
+    /// Reads an instruction written by `encode`. Oprands are given the kind the
+    /// operation expects, or `Immediate` if it doesn't use them.
+    pub fn decode(bytes: [u8; Self::SIZE]) -> Result<Self, String> {
+        let [header, a, b] = bytes;
+        let operation = Operation::from_opcode(header >> 4)
+            .ok_or_else(|| format!("{} is not a valid operation", header >> 4))?;
+        if header & 0b11 != 0 {
+            return Err(format!("{header:08b} has its unused bits set"));
+        }
+        let operation_args = OperationArgs::from_bits(header >> 2);
+        if !operation.is_alu_operation() && !matches!(operation_args, OperationArgs::None) {
+            return Err(format!("{:?} does not take ALU arguments", operation));
+        }
+        let (needed_a, needed_b) = operation.needed_oprands(&operation_args);
+        let oprand = |needed: Option<Oprand>, data| {
+            needed.map_or(Oprand::Immediate(data), |oprand| oprand.with_data(data))
+        };
+        Ok(Self {
+            a: oprand(needed_a, a),
+            b: oprand(needed_b, b),
+            operation,
+            operation_args,
+            source: None,
+        })
+    }
+}
+
+impl Program {
+    /// Encodes the program as an image of the whole ROM of the machine it was
+    /// assembled for, padded with NOOPs.
+    pub fn to_rom(&self) -> Result<Vec<u8>, String> {
+        if self.instructions.len() > self.machine.rom_lines {
+            return Err(format!(
+                "The program is {} lines long, but ROM only fits {}",
+                self.instructions.len(),
+                self.machine.rom_lines
+            ));
+        }
+        let mut rom: Vec<u8> = self
+            .instructions
+            .iter()
+            .flat_map(Instruction::encode)
+            .collect();
+        rom.resize(self.machine.rom_bytes(), 0);
+        Ok(rom)
+    }
+}
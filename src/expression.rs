@@ -0,0 +1,269 @@
+use std::fmt;
+
+// Constant expressions in oprands, like "(1 << 7) | 3" or "loop + 2". They are
+// folded into a single number when the program is assembled.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{number}"),
+            Token::Name(name) => write!(f, "{name}"),
+            Token::Operator(operator) => write!(f, "{operator}"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+const OPERATORS: [&str; 11] = ["<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~"];
+
+// How deeply brackets and unary operators can be nested, so a long line of
+// "(" can't overflow the stack
+const MAX_DEPTH: usize = 256;
+
+/// Evaluates `expression`, looking up the value of any names with `lookup`.
+pub fn evaluate(expression: &str, lookup: impl Fn(&str) -> Option<i64>) -> Result<i64, String> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+        expression,
+        lookup,
+    };
+    let value = parser.parse_expression(0, 0)?;
+    match parser.tokens.get(parser.position) {
+        Some(token) => Err(format!("Unexpected \"{token}\" in \"{expression}\"")),
+        None => Ok(value),
+    }
+}
+
+/// Reads a decimal, binary ("B" or "0b"), hex ("0x" or "$"), octal ("0o"),
+/// character ('A') or negative number. Digits can be separated by underscores.
+pub fn parse_number(str: &str) -> Result<i64, String> {
+    if str.starts_with('\'') {
+        return parse_char(str);
+    }
+    if let Some(number) = str.strip_prefix('-') {
+        // Only one minus, so "--5" isn't read as 5
+        if number.starts_with('-') {
+            return Err(format!("\"{str}\" is not a valid number"));
+        }
+        return parse_number(number).map(|number| -number);
+    }
+    let string = str.replace('_', "").to_ascii_uppercase();
+    let (digits, radix) = if let Some(hex) = string.strip_prefix("0X") {
+        (hex, 16)
+    } else if let Some(hex) = string.strip_prefix('$') {
+        (hex, 16)
+    } else if let Some(octal) = string.strip_prefix("0O") {
+        (octal, 8)
+    } else if let Some(binary) = string.strip_prefix("0B") {
+        (binary, 2)
+    } else if let Some(binary) = string.strip_prefix('B') {
+        (binary, 2)
+    } else {
+        (string.as_str(), 10)
+    };
+    if digits.is_empty() {
+        return Err(format!("\"{str}\" is missing a number"));
+    }
+    if !digits.chars().all(|char| char.is_digit(radix)) {
+        return Err(format!("\"{str}\" is not a valid number"));
+    }
+    i64::from_str_radix(digits, radix).map_err(|_| format!("\"{str}\" is too large"))
+}
+
+fn parse_char(str: &str) -> Result<i64, String> {
+    let Some(inner) = str
+        .strip_prefix('\'')
+        .and_then(|inner| inner.strip_suffix('\''))
+    else {
+        return Err(format!("{str} is missing its closing '"));
+    };
+    let char = match inner {
+        "\\'" => '\'',
+        "\\\\" => '\\',
+        "\\n" => '\n',
+        "\\t" => '\t',
+        "\\0" => '\0',
+        _ => {
+            let mut chars = inner.chars();
+            match (chars.next(), chars.next()) {
+                (Some(char), None) => char,
+                _ => return Err(format!("{str} must contain exactly one character")),
+            }
+        }
+    };
+    if char.is_ascii() {
+        Ok(char as i64)
+    } else {
+        Err(format!("{str} is not an ASCII character"))
+    }
+}
+
+/// Whether a word is shaped like the numbers read by `parse_number`.
+pub fn is_number_literal(str: &str) -> bool {
+    let binary = str.strip_prefix('B').is_some_and(|digits| {
+        !digits.is_empty() && digits.chars().all(|c| c == '_' || c.is_ascii_digit())
+    });
+    binary || str.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '$' | '\'' | '-'))
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = expression.trim_start();
+    while let Some(char) = rest.chars().next() {
+        let length = if char == '\'' {
+            // Find the closing quote, skipping over escaped characters
+            let mut escaped = false;
+            let end = rest
+                .char_indices()
+                .skip(1)
+                .find(|(_, char)| {
+                    let closes = !escaped && *char == '\'';
+                    escaped = !escaped && *char == '\\';
+                    closes
+                })
+                .map_or(rest.len(), |(end, _)| end + 1);
+            tokens.push(Token::Number(parse_char(&rest[..end])?));
+            end
+        } else if char.is_ascii_alphanumeric() || char == '_' || char == '$' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            if is_number_literal(word) {
+                tokens.push(Token::Number(parse_number(word)?));
+            } else {
+                tokens.push(Token::Name(word.to_string()));
+            }
+            end
+        } else if char == '(' {
+            tokens.push(Token::Open);
+            1
+        } else if char == ')' {
+            tokens.push(Token::Close);
+            1
+        } else if let Some(operator) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Operator(operator));
+            operator.len()
+        } else {
+            return Err(format!("Unexpected \"{char}\" in \"{expression}\""));
+        };
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// How tightly a binary operator binds, the same as in C
+fn precedence(operator: &str) -> Option<u8> {
+    match operator {
+        "|" => Some(1),
+        "^" => Some(2),
+        "&" => Some(3),
+        "<<" | ">>" => Some(4),
+        "+" | "-" => Some(5),
+        "*" | "/" | "%" => Some(6),
+        _ => None,
+    }
+}
+
+struct Parser<'a, F> {
+    tokens: Vec<Token>,
+    position: usize,
+    expression: &'a str,
+    lookup: F,
+}
+
+impl<F: Fn(&str) -> Option<i64>> Parser<'_, F> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn overflow(&self) -> String {
+        format!("\"{}\" is too large to calculate", self.expression)
+    }
+
+    // Parses the expression inside `depth` brackets and unary operators
+    fn parse_expression(&mut self, min_precedence: u8, depth: usize) -> Result<i64, String> {
+        let mut left = self.parse_unary(depth)?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            let operator = *operator;
+            let Some(precedence) = precedence(operator).filter(|p| *p >= min_precedence) else {
+                break;
+            };
+            self.position += 1;
+            let right = self.parse_expression(precedence + 1, depth)?;
+            left = self.apply(operator, left, right)?;
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self, depth: usize) -> Result<i64, String> {
+        if depth >= MAX_DEPTH {
+            return Err("Expression is nested too deeply".to_string());
+        }
+        match self.next() {
+            Some(Token::Number(number)) => Ok(number),
+            Some(Token::Name(name)) => {
+                (self.lookup)(&name).ok_or_else(|| format!("\"{name}\" is not defined"))
+            }
+            Some(Token::Operator("-")) => {
+                if let Some(Token::Operator("-")) = self.tokens.get(self.position) {
+                    return Err(format!(
+                        "\"{}\" has two minus signs in a row",
+                        self.expression
+                    ));
+                }
+                let value = self.parse_unary(depth + 1)?;
+                value.checked_neg().ok_or_else(|| self.overflow())
+            }
+            Some(Token::Operator("~")) => Ok(!self.parse_unary(depth + 1)?),
+            Some(Token::Open) => {
+                let value = self.parse_expression(0, depth + 1)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(value),
+                    _ => Err(format!("\"{}\" is missing a \")\"", self.expression)),
+                }
+            }
+            Some(token) => Err(format!("Unexpected \"{token}\" in \"{}\"", self.expression)),
+            None => Err(format!("\"{}\" ends unexpectedly", self.expression)),
+        }
+    }
+
+    fn apply(&self, operator: &str, left: i64, right: i64) -> Result<i64, String> {
+        let shift = || {
+            u32::try_from(right)
+                .ok()
+                .filter(|shift| *shift < 64)
+                .ok_or_else(|| format!("Can't shift by {right} in \"{}\"", self.expression))
+        };
+        let result = match operator {
+            "+" => left.checked_add(right),
+            "-" => left.checked_sub(right),
+            "*" => left.checked_mul(right),
+            "/" | "%" if right == 0 => {
+                return Err(format!("Division by zero in \"{}\"", self.expression))
+            }
+            "/" => left.checked_div(right),
+            "%" => left.checked_rem(right),
+            "&" => Some(left & right),
+            "|" => Some(left | right),
+            "^" => Some(left ^ right),
+            "<<" => left.checked_shl(shift()?),
+            ">>" => left.checked_shr(shift()?),
+            _ => None,
+        };
+        result.ok_or_else(|| self.overflow())
+    }
+}
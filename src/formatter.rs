@@ -0,0 +1,255 @@
+use crate::expression::is_number_literal;
+use crate::parser::mark_quoted;
+use crate::syntax::*;
+
+// Rewrites source in one canonical form:
+//
+// - Labels go on their own line, and they and directives start at column 0.
+//   Instructions are indented, and comments on their own line are indented
+//   like the code after them.
+// - Mnemonics are uppercase and directives are lowercase. Label and constant
+//   names are left as they were written.
+// - Oprands start in the same column, and so do comments after code, within
+//   each run of lines without a blank line between them.
+// - Numbers keep their base but are written as 0x2A, 0b1010 or 0o52, and
+//   registers as R1.
+// - Runs of blank lines become one, and the file ends with one line break.
+//
+// Comments are kept as they are. Lines with code that doesn't make sense are
+// left alone, so formatting never changes what a program means.
+
+const INDENT: &str = "    ";
+
+// A line of formatted source, before its columns are lined up
+enum Output {
+    Blank,
+    Verbatim(String),
+    Comment(String),
+    Label {
+        label: String,
+        comment: Option<String>,
+    },
+    Directive {
+        text: String,
+        comment: Option<String>,
+    },
+    Statement {
+        mnemonic: String,
+        oprands: String,
+        comment: Option<String>,
+    },
+}
+
+impl Output {
+    fn comment(&self) -> Option<&str> {
+        match self {
+            Output::Label { comment, .. }
+            | Output::Directive { comment, .. }
+            | Output::Statement { comment, .. } => comment.as_deref(),
+            _ => None,
+        }
+    }
+
+    // The code on the line, with oprands starting `mnemonic_width` after the
+    // indent
+    fn code(&self, mnemonic_width: usize) -> String {
+        match self {
+            Output::Blank | Output::Comment(_) => String::new(),
+            Output::Verbatim(text) => text.clone(),
+            Output::Label { label, .. } => format!("{label}:"),
+            Output::Directive { text, .. } => text.clone(),
+            Output::Statement {
+                mnemonic, oprands, ..
+            } if oprands.is_empty() => format!("{INDENT}{mnemonic}"),
+            Output::Statement {
+                mnemonic, oprands, ..
+            } => format!("{INDENT}{mnemonic:<mnemonic_width$} {oprands}"),
+        }
+    }
+}
+
+/// Formats a source file.
+pub fn format(source: &str) -> String {
+    let mut outputs = vec![];
+    for line in &SyntaxTree::parse(source).lines {
+        outputs.extend(format_line(line));
+    }
+    // No blank lines at the start or end, or more than one in a row
+    let mut kept: Vec<Output> = vec![];
+    for output in outputs {
+        let blank = matches!(output, Output::Blank);
+        let after_code = kept
+            .last()
+            .is_some_and(|last| !matches!(last, Output::Blank));
+        if blank && !after_code {
+            continue;
+        }
+        kept.push(output);
+    }
+    if matches!(kept.last(), Some(Output::Blank)) {
+        kept.pop();
+    }
+    let mut formatted = String::new();
+    for paragraph in kept.split(|output| matches!(output, Output::Blank)) {
+        if !formatted.is_empty() {
+            formatted.push('\n');
+        }
+        format_paragraph(paragraph, &mut formatted);
+    }
+    formatted
+}
+
+fn format_line(line: &SyntaxLine) -> Vec<Output> {
+    let comment = line.comment().map(|comment| comment.trim_end().to_string());
+    if line.is_blank() {
+        return vec![Output::Blank];
+    }
+    let unknown = line
+        .tokens
+        .iter()
+        .any(|token| token.kind == TokenKind::Unknown);
+    if unknown {
+        return vec![Output::Verbatim(line.to_string().trim_end().to_string())];
+    }
+    let mut outputs = vec![];
+    if let Some(label) = line.label() {
+        outputs.push(Output::Label {
+            label: label.to_string(),
+            comment: None,
+        });
+    }
+    let oprands: Vec<&str> = line.oprands().collect();
+    match line.name() {
+        Some(name) if name.kind == TokenKind::Directive => {
+            let directive = name.text.to_ascii_lowercase();
+            let mut words = vec![directive.clone()];
+            match directive.as_str() {
+                // The file name is left exactly as written
+                ".include" => {
+                    let argument: String = line
+                        .tokens
+                        .iter()
+                        .filter(|token| token.column > name.column)
+                        .take_while(|token| token.kind != TokenKind::Comment)
+                        .map(|token| token.text.as_str())
+                        .collect();
+                    words.push(argument.trim().to_string());
+                }
+                // The name and parameters are only names
+                ".macro" => words.extend(oprands.iter().map(|word| word.to_string())),
+                _ => {
+                    words.extend(oprands.first().map(|name| name.to_string()));
+                    words.extend(oprands.iter().skip(1).map(|value| format_oprand(value)));
+                }
+            }
+            words.retain(|word| !word.is_empty());
+            outputs.push(Output::Directive {
+                text: words.join(" "),
+                comment,
+            });
+        }
+        Some(name) => {
+            let oprands: Vec<String> = oprands.iter().map(|word| format_oprand(word)).collect();
+            outputs.push(Output::Statement {
+                mnemonic: name.text.to_ascii_uppercase(),
+                oprands: oprands.join(" "),
+                comment,
+            });
+        }
+        None => match outputs.last_mut() {
+            Some(Output::Label {
+                comment: label_comment,
+                ..
+            }) => *label_comment = comment,
+            _ => outputs.extend(comment.map(Output::Comment)),
+        },
+    }
+    outputs
+}
+
+// Lines up the oprands and comments of a run of lines
+fn format_paragraph(outputs: &[Output], formatted: &mut String) {
+    let mnemonic_width = outputs
+        .iter()
+        .filter_map(|output| match output {
+            Output::Statement {
+                mnemonic, oprands, ..
+            } if !oprands.is_empty() => Some(mnemonic.len()),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let comment_column = outputs
+        .iter()
+        .filter(|output| output.comment().is_some())
+        .map(|output| output.code(mnemonic_width).len())
+        .max()
+        .unwrap_or(0);
+    for (index, output) in outputs.iter().enumerate() {
+        let line = match (output, output.comment()) {
+            (Output::Comment(comment), _) => {
+                // Indented like the next line with code
+                let next = outputs[index..]
+                    .iter()
+                    .find(|output| !matches!(output, Output::Comment(_)));
+                match next {
+                    Some(Output::Statement { .. }) => format!("{INDENT}{comment}"),
+                    _ => comment.clone(),
+                }
+            }
+            (_, Some(comment)) => {
+                format!("{:<comment_column$} {comment}", output.code(mnemonic_width))
+            }
+            (_, None) => output.code(mnemonic_width),
+        };
+        formatted.push_str(&line);
+        formatted.push('\n');
+    }
+}
+
+// Writes an oprand's numbers and registers the same way every time
+fn format_oprand(oprand: &str) -> String {
+    let mut formatted = String::new();
+    let mut word = String::new();
+    for (_, char, quoted) in mark_quoted(oprand) {
+        if !quoted && (char.is_ascii_alphanumeric() || char == '_' || char == '$') {
+            word.push(char);
+            continue;
+        }
+        formatted.push_str(&format_word(&std::mem::take(&mut word)));
+        formatted.push(char);
+    }
+    formatted.push_str(&format_word(&word));
+    formatted
+}
+
+fn format_word(word: &str) -> String {
+    let upper = word.to_ascii_uppercase();
+    if let Some(index) = upper
+        .strip_prefix('R')
+        .filter(|index| is_number_literal(index))
+    {
+        return format!("R{}", format_number(index));
+    }
+    if is_number_literal(&upper) {
+        return format_number(&upper);
+    }
+    word.to_string()
+}
+
+// Takes an uppercase number
+fn format_number(number: &str) -> String {
+    let prefixes = [
+        ("0X", "0x"),
+        ("$", "0x"),
+        ("0O", "0o"),
+        ("0B", "0b"),
+        ("B", "0b"),
+    ];
+    for (prefix, canonical) in prefixes {
+        if let Some(digits) = number.strip_prefix(prefix) {
+            return format!("{canonical}{digits}");
+        }
+    }
+    number.to_string()
+}
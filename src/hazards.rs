@@ -0,0 +1,249 @@
+use crate::isa::*;
+use crate::parser::*;
+use std::ops::Range;
+
+// Finds instructions that would read a value other than the one the program
+// means them to, because of how the pipeline runs them.
+//
+// Registers are written when an instruction writes back and read when a later
+// one executes. `Emulator::clock` writes back before it executes, so the next
+// instruction already reads the new value and registers need no NOOPs between
+// them. MOV and OUT read their register when they write back, by which time
+// every earlier instruction has written back too.
+//
+// Branches change the program counter when they execute. The clock fetches
+// after it executes, so only the instruction already decoded behind a branch
+// runs before the branch takes effect, in its delay slot.
+//
+// The accumalator and flags are another matter. Every instruction goes
+// through the ALU when it executes, including IMM, MOV, OUT, NOOP and the
+// branches, so U and X arguments and BIE only see what the instruction run
+// just before them left there. NOOPs don't help, they overwrite them too.
+
+/// The stages an instruction's registers and branches are used in, counting
+/// from fetch as stage 0.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    /// The stage oprands are read from registers in
+    pub read_stage: usize,
+    /// The stage results are written to registers in
+    pub write_stage: usize,
+    /// The stage branches change the program counter in
+    pub branch_stage: usize,
+    /// Whether each clock runs the later stages first, so results are written
+    /// before oprands are read and branches are taken before the next fetch
+    pub later_stages_first: bool,
+}
+
+impl Pipeline {
+    /// The pipeline `Emulator::clock` runs: fetch, decode, execute, then write
+    /// back, with write back done first in each clock.
+    pub const EMULATOR: Pipeline = Pipeline {
+        read_stage: 2,
+        write_stage: 3,
+        branch_stage: 2,
+        later_stages_first: true,
+    };
+
+    /// How many lines after an instruction one has to be to read the register
+    /// it writes.
+    pub fn register_distance(&self) -> usize {
+        let write = self.write_stage + usize::from(!self.later_stages_first);
+        write.saturating_sub(self.read_stage).max(1)
+    }
+
+    /// How many instructions after a branch run before it takes effect.
+    pub fn delay_slots(&self) -> usize {
+        self.branch_stage
+            .saturating_sub(usize::from(self.later_stages_first))
+    }
+}
+
+/// A value kept by the ALU between instructions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AluValue {
+    Accumalator,
+    Flags,
+}
+
+impl AluValue {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AluValue::Accumalator => "accumalator",
+            AluValue::Flags => "flags",
+        }
+    }
+}
+
+/// An instruction that would read the wrong value. Instructions are referred
+/// to by their address.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hazard {
+    /// `reader` reads `register` before `writer` has written it, and needs
+    /// `noops` more lines between them.
+    Register {
+        reader: usize,
+        writer: usize,
+        register: u8,
+        noops: usize,
+    },
+    /// `reader` uses the accumalator or flags, but `overwriter` runs just
+    /// before it. `intended` is the ALU instruction before that, if there is
+    /// one.
+    Alu {
+        reader: usize,
+        overwriter: usize,
+        intended: Option<usize>,
+        value: AluValue,
+    },
+}
+
+// Registers read when the instruction executes, as opposed to writes back
+fn executed_reads(instruction: &Instruction) -> Vec<u8> {
+    let (a, b) = (instruction.a.data(), instruction.b.data());
+    let alu = instruction.operation.definition().alu;
+    let reads = match alu {
+        AluFunction::None => vec![],
+        _ if alu.reads_a() && !instruction.operation_args.uses_accumalator() => vec![a, b],
+        _ => vec![b],
+    };
+    // R0 always reads as 0, whatever was written to it
+    reads
+        .into_iter()
+        .filter(|register| *register != 0)
+        .collect()
+}
+
+/// Every register an instruction reads, whichever stage it reads them in.
+pub fn read_registers(instruction: &Instruction) -> Vec<u8> {
+    let mut reads = executed_reads(instruction);
+    let b = instruction.b.data();
+    let copies = matches!(instruction.write_back(), WriteBack::Copy | WriteBack::Out);
+    if copies && b != 0 {
+        reads.push(b);
+    }
+    reads
+}
+
+/// The register an instruction writes when it writes back, if it writes one.
+pub fn written_register(instruction: &Instruction) -> Option<u8> {
+    let writes = matches!(
+        instruction.write_back(),
+        WriteBack::Immediate | WriteBack::Copy | WriteBack::Result
+    );
+    Some(instruction.a.data()).filter(|register| writes && *register != 0)
+}
+
+/// What an instruction reads from the ALU, if anything.
+pub fn alu_read(instruction: &Instruction) -> Option<AluValue> {
+    if instruction.operation_args.uses_accumalator() {
+        Some(AluValue::Accumalator)
+    } else if instruction.operation.definition().branch == Branch::IfEqual {
+        Some(AluValue::Flags)
+    } else {
+        None
+    }
+}
+
+/// Whether an instruction leaves a result in the accumalator and flags, rather
+/// than whatever the ALU makes of its oprands.
+pub fn sets_alu(instruction: &Instruction) -> bool {
+    instruction.operation.definition().alu != AluFunction::None
+}
+
+/// Whether an instruction is a JMP or BIE.
+pub fn is_branch(instruction: &Instruction) -> bool {
+    instruction.operation.definition().branch != Branch::Never
+}
+
+/// Whether an instruction changes a register, a port or where the program
+/// goes, rather than only the accumalator and flags.
+pub fn has_side_effects(instruction: &Instruction) -> bool {
+    written_register(instruction).is_some()
+        || instruction.write_back() == WriteBack::Out
+        || is_branch(instruction)
+}
+
+/// The addresses of the instructions that run after the branch at `branch`
+/// before it takes effect.
+pub fn shadow(instructions: &[Instruction], branch: usize, pipeline: &Pipeline) -> Range<usize> {
+    let end = (branch + 1 + pipeline.delay_slots()).min(instructions.len());
+    (branch + 1).min(end)..end
+}
+
+/// The branch the instruction at `address` runs in the shadow of, if any.
+pub fn shadowing_branch(
+    instructions: &[Instruction],
+    address: usize,
+    pipeline: &Pipeline,
+) -> Option<usize> {
+    (address.saturating_sub(pipeline.delay_slots())..address)
+        .rev()
+        .find(|branch| is_branch(&instructions[*branch]))
+}
+
+// The instructions that can run just before the one at `address`. Branches
+// take effect late, so it's the last line in a branch's shadow that runs
+// before its target, and the line after that is only reached from the branch
+// if it can fall through.
+fn run_before(instructions: &[Instruction], address: usize, pipeline: &Pipeline) -> Vec<usize> {
+    let slots = pipeline.delay_slots();
+    let mut before = vec![];
+    let skipped = address
+        .checked_sub(slots + 1)
+        .is_some_and(|jump| instructions[jump].operation.definition().branch == Branch::Always);
+    if address > 0 && !skipped {
+        before.push(address - 1);
+    }
+    for (branch, instruction) in instructions.iter().enumerate() {
+        let last = branch + slots;
+        if is_branch(instruction)
+            && instruction.a.data() as usize == address
+            && last < instructions.len()
+            && !before.contains(&last)
+        {
+            before.push(last);
+        }
+    }
+    before
+}
+
+/// Finds every hazard in a program, following branches to see what runs
+/// before each instruction.
+pub fn find_hazards(instructions: &[Instruction], pipeline: &Pipeline) -> Vec<Hazard> {
+    let mut hazards = vec![];
+    let distance = pipeline.register_distance();
+    for (reader, instruction) in instructions.iter().enumerate() {
+        for register in executed_reads(instruction) {
+            let writer = (reader.saturating_sub(distance - 1)..reader)
+                .rev()
+                .find(|writer| written_register(&instructions[*writer]) == Some(register));
+            if let Some(writer) = writer {
+                hazards.push(Hazard::Register {
+                    reader,
+                    writer,
+                    register,
+                    noops: distance - (reader - writer),
+                });
+            }
+        }
+        let Some(value) = alu_read(instruction) else {
+            continue;
+        };
+        for overwriter in run_before(instructions, reader, pipeline) {
+            if sets_alu(&instructions[overwriter]) {
+                continue;
+            }
+            let intended = (0..overwriter)
+                .rev()
+                .find(|address| sets_alu(&instructions[*address]));
+            hazards.push(Hazard::Alu {
+                reader,
+                overwriter,
+                intended,
+                value,
+            });
+        }
+    }
+    hazards
+}
@@ -0,0 +1,296 @@
+use crate::parser::*;
+
+// Every instruction the computer has, and what each part of the computer does
+// with it. The assembler, encoder, emulator, hazard checks, disassembler and
+// documentation all read it from here, so adding an instruction means adding
+// a `Operation` variant and a row to `INSTRUCTION_SET`.
+//
+// Every instruction goes through the ALU when it executes, then writes back.
+// The ALU works out `alu` from oprands A and B, or from the accumalator
+// instead of A when given a U or X argument, and sets the accumalator and
+// flags. Write back then does `write_back`. ALU arguments decide whether
+// instructions that take them write their result:
+//
+// none  A + B              only the accumalator and flags are set
+// S     A + B              the result is written to register A
+// U     accumalator + B    the result is written to register A
+// X     accumalator + B    only the accumalator and flags are set
+
+/// What the ALU works out from its inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AluFunction {
+    /// Nothing, the accumalator is set to 0
+    None,
+    Add,
+    /// A + B + 1
+    AddCarry,
+    /// B shifted right one bit
+    ShiftRight,
+    /// B with its bits inverted
+    Not,
+}
+
+/// What an instruction does when it writes back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteBack {
+    Nothing,
+    /// Writes the number B to register A
+    Immediate,
+    /// Copies register B to register A
+    Copy,
+    /// Writes the accumalator to register A
+    Result,
+    /// Copies register B to port A
+    Out,
+}
+
+/// When an instruction changes the program counter, to oprand A.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Branch {
+    Never,
+    Always,
+    /// When the last ALU instruction found its inputs equal
+    IfEqual,
+}
+
+/// One instruction of the instruction set.
+#[derive(Debug)]
+pub struct Definition {
+    pub operation: Operation,
+    pub mnemonic: &'static str,
+    /// Other names it can be written as
+    pub aliases: &'static [&'static str],
+    /// The number stored in the top 4 bits of its first byte in ROM
+    pub opcode: u8,
+    /// The kinds of oprand it takes (A, B) without ALU arguments, holding 0
+    pub oprands: (Option<Oprand>, Option<Oprand>),
+    /// Whether it can be given S, U or X ALU arguments
+    pub alu_arguments: bool,
+    pub alu: AluFunction,
+    pub write_back: WriteBack,
+    pub branch: Branch,
+    /// What it does, in a sentence or two
+    pub summary: &'static str,
+}
+
+pub static INSTRUCTION_SET: [Definition; 10] = [
+    Definition {
+        operation: Operation::NOOP,
+        mnemonic: "NOOP",
+        aliases: &["NOP"],
+        opcode: 0,
+        oprands: (None, None),
+        alu_arguments: false,
+        alu: AluFunction::None,
+        write_back: WriteBack::Nothing,
+        branch: Branch::Never,
+        summary: "Does nothing for a clock.",
+    },
+    Definition {
+        operation: Operation::IMM,
+        mnemonic: "IMM",
+        aliases: &[],
+        opcode: 1,
+        oprands: (Some(Oprand::Register(0)), Some(Oprand::Immediate(0))),
+        alu_arguments: false,
+        alu: AluFunction::None,
+        write_back: WriteBack::Immediate,
+        branch: Branch::Never,
+        summary: "Puts the number B in register A.",
+    },
+    Definition {
+        operation: Operation::MOV,
+        mnemonic: "MOV",
+        aliases: &[],
+        opcode: 2,
+        oprands: (Some(Oprand::Register(0)), Some(Oprand::Register(0))),
+        alu_arguments: false,
+        alu: AluFunction::None,
+        write_back: WriteBack::Copy,
+        branch: Branch::Never,
+        summary: "Copies register B into register A.",
+    },
+    Definition {
+        operation: Operation::ADD,
+        mnemonic: "ADD",
+        aliases: &[],
+        opcode: 3,
+        oprands: (Some(Oprand::Register(0)), Some(Oprand::Register(0))),
+        alu_arguments: true,
+        alu: AluFunction::Add,
+        write_back: WriteBack::Result,
+        branch: Branch::Never,
+        summary: "Adds A and B.",
+    },
+    Definition {
+        operation: Operation::ADDC,
+        mnemonic: "ADDC",
+        aliases: &[],
+        opcode: 4,
+        oprands: (Some(Oprand::Register(0)), Some(Oprand::Register(0))),
+        alu_arguments: true,
+        alu: AluFunction::AddCarry,
+        write_back: WriteBack::Result,
+        branch: Branch::Never,
+        summary: "Adds A, B and 1.",
+    },
+    Definition {
+        operation: Operation::SHR,
+        mnemonic: "SHR",
+        aliases: &[],
+        opcode: 5,
+        oprands: (Some(Oprand::Register(0)), Some(Oprand::Register(0))),
+        alu_arguments: false,
+        alu: AluFunction::ShiftRight,
+        write_back: WriteBack::Result,
+        branch: Branch::Never,
+        summary: "Shifts register B right one bit, into register A.",
+    },
+    Definition {
+        operation: Operation::NOT,
+        mnemonic: "NOT",
+        aliases: &[],
+        opcode: 6,
+        oprands: (Some(Oprand::Register(0)), Some(Oprand::Register(0))),
+        alu_arguments: false,
+        alu: AluFunction::Not,
+        write_back: WriteBack::Result,
+        branch: Branch::Never,
+        summary: "Inverts the bits of register B, into register A.",
+    },
+    Definition {
+        operation: Operation::OUT,
+        mnemonic: "OUT",
+        aliases: &[],
+        opcode: 7,
+        oprands: (Some(Oprand::Port(0)), Some(Oprand::Register(0))),
+        alu_arguments: false,
+        alu: AluFunction::None,
+        write_back: WriteBack::Out,
+        branch: Branch::Never,
+        summary: "Shows register B on port A.",
+    },
+    Definition {
+        operation: Operation::JMP,
+        mnemonic: "JMP",
+        aliases: &[],
+        opcode: 8,
+        oprands: (Some(Oprand::Immediate(0)), None),
+        alu_arguments: false,
+        alu: AluFunction::None,
+        write_back: WriteBack::Nothing,
+        branch: Branch::Always,
+        summary: "Jumps to line A. The line after it runs first, in its delay slot.",
+    },
+    Definition {
+        operation: Operation::BIE,
+        mnemonic: "BIE",
+        aliases: &[],
+        opcode: 9,
+        oprands: (Some(Oprand::Immediate(0)), None),
+        alu_arguments: false,
+        alu: AluFunction::None,
+        write_back: WriteBack::Nothing,
+        branch: Branch::IfEqual,
+        summary: "Jumps to line A if the last ALU instruction found its inputs equal. \
+                  The line after it runs first, in its delay slot.",
+    },
+];
+
+impl Operation {
+    /// The operation's row of `INSTRUCTION_SET`.
+    pub fn definition(&self) -> &'static Definition {
+        INSTRUCTION_SET
+            .iter()
+            .find(|definition| definition.operation == *self)
+            .expect("every operation is in the instruction set")
+    }
+
+    /// The operation with a mnemonic or alias, without ALU arguments.
+    pub fn from_mnemonic(name: &str) -> Option<Self> {
+        INSTRUCTION_SET
+            .iter()
+            .find(|definition| definition.mnemonic == name || definition.aliases.contains(&name))
+            .map(|definition| definition.operation.clone())
+    }
+}
+
+impl OperationArgs {
+    /// Whether the ALU reads the accumalator instead of register A.
+    pub fn uses_accumalator(&self) -> bool {
+        matches!(self, OperationArgs::U | OperationArgs::X)
+    }
+
+    /// Whether the result is written to register A.
+    pub fn writes_result(&self) -> bool {
+        matches!(self, OperationArgs::S | OperationArgs::U)
+    }
+}
+
+impl AluFunction {
+    /// Whether it uses input A, as well as B.
+    pub fn reads_a(&self) -> bool {
+        matches!(self, AluFunction::Add | AluFunction::AddCarry)
+    }
+
+    /// Works out the result, which may not fit in 8 bits.
+    pub fn apply(&self, a: u16, b: u16) -> u16 {
+        match self {
+            AluFunction::None => 0,
+            AluFunction::Add => a + b,
+            AluFunction::AddCarry => a + b + 1,
+            AluFunction::ShiftRight => b >> 1,
+            AluFunction::Not => !b,
+        }
+    }
+}
+
+impl Definition {
+    /// How the instruction is written with ALU arguments `args`, like
+    /// "SADD RA RB".
+    pub fn usage(&self, args: &OperationArgs) -> String {
+        let mut usage = format!("{args}{}", self.mnemonic);
+        let (a, b) = self.operation.needed_oprands(args);
+        for (oprand, letter) in [(a, "A"), (b, "B")] {
+            usage += match oprand {
+                Some(Oprand::Register(_)) => " R",
+                Some(Oprand::Port(_)) => " %",
+                Some(Oprand::MemoryAddress(_)) => " #",
+                Some(Oprand::Immediate(_)) => " ",
+                None => continue,
+            };
+            usage += letter;
+        }
+        usage
+    }
+}
+
+impl Instruction {
+    /// What the instruction does when it writes back, given its ALU arguments.
+    pub fn write_back(&self) -> WriteBack {
+        let definition = self.operation.definition();
+        match definition.write_back {
+            WriteBack::Result
+                if definition.alu_arguments && !self.operation_args.writes_result() =>
+            {
+                WriteBack::Nothing
+            }
+            write_back => write_back,
+        }
+    }
+}
+
+/// A Markdown table of every instruction, for the README.
+pub fn reference() -> String {
+    let mut table =
+        "| Instruction | Opcode | Does |\n| ----------- | ------ | ---- |\n".to_string();
+    for definition in &INSTRUCTION_SET {
+        let usage = definition.usage(&OperationArgs::None);
+        let mut summary = definition.summary.to_string();
+        if definition.alu_arguments {
+            summary += " Takes S, U and X arguments.";
+        }
+        table += &format!("| `{usage}` | {} | {summary} |\n", definition.opcode);
+    }
+    table
+}
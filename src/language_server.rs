@@ -0,0 +1,574 @@
+use crate::diagnostic::*;
+use crate::isa::*;
+use crate::machine::Machine;
+use crate::parser::*;
+use crate::pseudo::Pseudo;
+use crate::syntax::*;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+// A language server for editors that speak the Language Server Protocol,
+// talking JSON-RPC over stdin and stdout. Programs are assembled by the real
+// assembler whenever they change, so editors show the same errors and warnings
+// as `electron assemble`. Highlighting and hovers come from the syntax tree,
+// so they work on lines that don't assemble too.
+//
+// Only what the server needs of the protocol is read, and documents are
+// always sent whole. Positions are in UTF-16 code units, as the protocol
+// wants by default. Programs are assembled for Electron V1 unless the client
+// names another machine in its initialization options, as `"machine"`.
+
+// What each kind of semantic token is called, in the order editors are told
+const TOKEN_TYPES: [&str; 9] = [
+    "keyword",
+    "macro",
+    "function",
+    "variable",
+    "parameter",
+    "number",
+    "string",
+    "operator",
+    "comment",
+];
+
+#[derive(Default)]
+struct Document {
+    text: String,
+    // The names from the last time the document assembled, so completions
+    // still work while it's half written
+    symbols: Vec<Symbol>,
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+    options: AssemblyOptions,
+}
+
+/// Runs the server until the client tells it to exit or closes `input`.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        if method == "exit" {
+            break;
+        }
+        let params = &message["params"];
+        let (result, notifications) = server.handle(method, params);
+        // Only requests have an id and get a response
+        if let Some(id) = message.get("id") {
+            let response = match result {
+                Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                None => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32601, "message": format!("Unknown method {method}") }
+                }),
+            };
+            write_message(&mut output, &response)?;
+        }
+        for notification in notifications {
+            write_message(&mut output, &notification)?;
+        }
+    }
+    Ok(())
+}
+
+// Reads one message, which is a Content-Length header and then that many
+// bytes of JSON. Returns `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Message is missing its Content-Length",
+        ));
+    };
+    // Read into a growing buffer, so a wrong length can't allocate more than
+    // was actually sent
+    let mut body = vec![];
+    input.by_ref().take(length as u64).read_to_end(&mut body)?;
+    if body.len() < length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Message is shorter than its Content-Length",
+        ));
+    }
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+impl Server {
+    // Handles a request or notification, returning the result for requests
+    // the server knows, and any notifications to send back
+    fn handle(&mut self, method: &str, params: &Value) -> (Option<Value>, Vec<Value>) {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let result = match method {
+            "initialize" => return self.initialize(params),
+            // Nothing needs tidying up before exiting
+            "shutdown" => Value::Null,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                return (None, vec![self.update(&uri, text)]);
+            }
+            "textDocument/didChange" => {
+                // Documents are synced whole, so the last change is all of it
+                let changes = params["contentChanges"].as_array();
+                let Some(text) = changes.and_then(|changes| changes.last()?["text"].as_str())
+                else {
+                    return (None, vec![]);
+                };
+                return (None, vec![self.update(&uri, text)]);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                let clear = json!({ "uri": uri, "diagnostics": [] });
+                return (
+                    None,
+                    vec![notification("textDocument/publishDiagnostics", clear)],
+                );
+            }
+            "textDocument/hover" => self.hover(&uri, &params["position"]),
+            "textDocument/completion" => self.completions(&uri),
+            "textDocument/semanticTokens/full" => self.semantic_tokens(&uri),
+            // Notifications like "initialized" need nothing doing, and
+            // requests the server doesn't know get an error
+            _ => return (None, vec![]),
+        };
+        (Some(result), vec![])
+    }
+
+    // Says what the server can do, and picks the machine programs are for
+    fn initialize(&mut self, params: &Value) -> (Option<Value>, Vec<Value>) {
+        let mut notifications = vec![];
+        if let Some(name) = params["initializationOptions"]["machine"].as_str() {
+            match Machine::load(name) {
+                Ok(machine) => self.options.machine = machine,
+                Err(error) => notifications.push(notification(
+                    "window/showMessage",
+                    json!({ "type": 1, "message": error }),
+                )),
+            }
+        }
+        let capabilities = json!({
+            "capabilities": {
+                "textDocumentSync": 1,
+                "hoverProvider": true,
+                "completionProvider": {},
+                "semanticTokensProvider": {
+                    "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+                    "full": true
+                }
+            },
+            "serverInfo": { "name": "electron", "version": env!("CARGO_PKG_VERSION") }
+        });
+        (Some(capabilities), notifications)
+    }
+
+    // Stores a document's new text and assembles it, returning its diagnostics
+    fn update(&mut self, uri: &str, text: &str) -> Value {
+        let document = self.documents.entry(uri.to_string()).or_default();
+        document.text = text.to_string();
+        let file_name = path_from_uri(uri);
+        let result = ProgramLoader::assemble_file_with(&file_name, text, &self.options);
+        let diagnostics = match result {
+            Ok(program) => {
+                document.symbols = program.symbols;
+                program.warnings
+            }
+            Err(diagnostics) => diagnostics,
+        };
+        let diagnostics: Vec<Value> = diagnostics
+            .iter()
+            .filter_map(|diagnostic| to_lsp_diagnostic(uri, text, diagnostic))
+            .collect();
+        let params = json!({ "uri": uri, "diagnostics": diagnostics });
+        notification("textDocument/publishDiagnostics", params)
+    }
+
+    fn hover(&self, uri: &str, position: &Value) -> Value {
+        let Some(document) = self.documents.get(uri) else {
+            return Value::Null;
+        };
+        let Some((token, range)) = token_at(&document.text, position) else {
+            return Value::Null;
+        };
+        let docs = match token.kind {
+            TokenKind::Mnemonic => mnemonic_docs(&token.text.to_ascii_uppercase()),
+            TokenKind::Oprand | TokenKind::Label => {
+                let name = token.text.trim_end_matches(':').trim().to_ascii_uppercase();
+                document
+                    .symbols
+                    .iter()
+                    .find(|symbol| symbol.name == name)
+                    .map(symbol_docs)
+            }
+            _ => None,
+        };
+        match docs {
+            Some(docs) => json!({
+                "contents": { "kind": "markdown", "value": docs },
+                "range": range
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn completions(&self, uri: &str) -> Value {
+        const KEYWORD: u8 = 14;
+        const VARIABLE: u8 = 6;
+        const FUNCTION: u8 = 3;
+        const CONSTANT: u8 = 21;
+        let mut items = vec![];
+        let mut item = |label: String, kind: u8, detail: String| {
+            items.push(json!({ "label": label, "kind": kind, "detail": detail }));
+        };
+        for name in mnemonic_names() {
+            let detail = match Pseudo::from_name(&name) {
+                Some(_) => "pseudo-instruction",
+                None => "instruction",
+            };
+            item(name, KEYWORD, detail.to_string());
+        }
+        for directive in [".equ", ".alias", ".macro", ".endm", ".include"] {
+            item(directive.to_string(), KEYWORD, "directive".to_string());
+        }
+        let machine = &self.options.machine;
+        for register in 0..machine.registers {
+            item(format!("R{register}"), VARIABLE, "register".to_string());
+        }
+        for port in 0..machine.output_ports {
+            item(format!("%{port}"), VARIABLE, "port".to_string());
+        }
+        let symbols = self.documents.get(uri).map_or(&[][..], |d| &d.symbols);
+        for symbol in symbols {
+            let (kind, detail) = match symbol.kind {
+                SymbolKind::Label => (FUNCTION, format!("label, address {}", symbol.value)),
+                SymbolKind::Constant => (CONSTANT, format!("constant, {}", symbol.value)),
+                SymbolKind::Alias => (VARIABLE, format!("alias of {}", symbol.value)),
+            };
+            item(symbol.name.clone(), kind, detail);
+        }
+        Value::Array(items)
+    }
+
+    fn semantic_tokens(&self, uri: &str) -> Value {
+        let Some(document) = self.documents.get(uri) else {
+            return json!({ "data": [] });
+        };
+        let mut data = vec![];
+        let (mut last_line, mut last_start) = (0, 0);
+        let tree = SyntaxTree::parse(&document.text);
+        for (line_number, line) in tree.lines.iter().enumerate() {
+            let text: String = line
+                .tokens
+                .iter()
+                .map(|token| token.text.as_str())
+                .collect();
+            for (column, length, token_type) in highlights(line) {
+                let start = utf16_length(&text[..column]);
+                let length = utf16_length(&text[column..column + length]);
+                if line_number != last_line {
+                    last_start = 0;
+                }
+                data.extend([
+                    line_number - last_line,
+                    start - last_start,
+                    length,
+                    token_type,
+                    0,
+                ]);
+                (last_line, last_start) = (line_number, start);
+            }
+        }
+        json!({ "data": data })
+    }
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+// Turns a "file://" URI into the path the assembler finds includes from
+fn path_from_uri(uri: &str) -> String {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut decoded = vec![];
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        let escaped = (byte == b'%')
+            .then(|| {
+                let hex = [bytes.next()?, bytes.next()?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()
+            })
+            .flatten();
+        decoded.push(escaped.unwrap_or(byte));
+    }
+    let path = String::from_utf8_lossy(&decoded).to_string();
+    // Windows paths look like "/C:/programs/heart.elt"
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_string(),
+        _ => path,
+    }
+}
+
+fn utf16_length(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+// An LSP position of a column of a line of `text`
+fn position(text: &str, line: usize, column: usize) -> Value {
+    let line_text = text.lines().nth(line).unwrap_or_default();
+    let mut column = column.min(line_text.len());
+    while !line_text.is_char_boundary(column) {
+        column -= 1;
+    }
+    json!({ "line": line, "character": utf16_length(&line_text[..column]) })
+}
+
+fn range(text: &str, line: usize, column: usize, length: usize) -> Value {
+    json!({
+        "start": position(text, line, column),
+        "end": position(text, line, column + length),
+    })
+}
+
+fn to_lsp_diagnostic(uri: &str, text: &str, diagnostic: &Diagnostic) -> Option<Value> {
+    // Problems in included files are shown on the line including them
+    let (place, message) = match &diagnostic.file {
+        None => (diagnostic, diagnostic.message.clone()),
+        Some(file) => (
+            diagnostic
+                .notes
+                .iter()
+                .rev()
+                .find(|note| note.file.is_none())?,
+            format!("In {file}: {}", diagnostic.message),
+        ),
+    };
+    let message = match &diagnostic.help {
+        Some(help) => format!("{message}\nhelp: {help}"),
+        None => message,
+    };
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+    let related: Vec<Value> = diagnostic
+        .notes
+        .iter()
+        .filter(|note| note.file.is_none() && diagnostic.file.is_none())
+        .map(|note| {
+            json!({
+                "location": {
+                    "uri": uri,
+                    "range": range(text, note.line, note.column, note.length)
+                },
+                "message": note.message
+            })
+        })
+        .collect();
+    Some(json!({
+        "range": range(text, place.line, place.column, place.length),
+        "severity": severity,
+        "source": "electron",
+        "message": message,
+        "relatedInformation": related
+    }))
+}
+
+// The token at an LSP position, and its range
+fn token_at(text: &str, at: &Value) -> Option<(Token, Value)> {
+    let line_number = at["line"].as_u64()? as usize;
+    let character = at["character"].as_u64()? as usize;
+    let tree = SyntaxTree::parse(text);
+    let line = tree.lines.get(line_number)?;
+    let line_text: String = line
+        .tokens
+        .iter()
+        .map(|token| token.text.as_str())
+        .collect();
+    let token = line.tokens.iter().find(|token| {
+        let start = utf16_length(&line_text[..token.column]);
+        let end = start + utf16_length(&token.text);
+        (start..end).contains(&character)
+    })?;
+    let range = range(text, line_number, token.column, token.text.len());
+    Some((token.clone(), range))
+}
+
+// Every instruction and pseudo-instruction name, with their ALU arguments
+fn mnemonic_names() -> Vec<String> {
+    let mut names = vec![];
+    for definition in &INSTRUCTION_SET {
+        let name = definition.mnemonic;
+        names.push(name.to_string());
+        if definition.alu_arguments {
+            names.extend(["S", "U", "X"].map(|prefix| format!("{prefix}{name}")));
+        }
+    }
+    names.extend(Pseudo::NAMES.map(str::to_string));
+    names
+}
+
+// What an instruction does, for hovers
+fn mnemonic_docs(name: &str) -> Option<String> {
+    if let Some(pseudo) = Pseudo::from_name(name) {
+        return Some(pseudo_docs(name, pseudo));
+    }
+    let (operation, args) = operation_from_str(&[name.to_string()]).ok()?;
+    let definition = operation.definition();
+    let mut usage = definition.usage(&args);
+    // Aliases are written the way they were hovered, so "NOP" stays "NOP"
+    if definition
+        .aliases
+        .iter()
+        .any(|alias| alias.eq_ignore_ascii_case(name))
+    {
+        usage = usage.replacen(definition.mnemonic, name, 1);
+    }
+    let description = definition.summary;
+    let args = match (operation.is_alu_operation(), args) {
+        (false, _) => "",
+        (true, OperationArgs::None) => {
+            " Only the accumalator and flags are set. Prefix it with S, U or X to do more."
+        }
+        (true, OperationArgs::S) => " The result is written to register A.",
+        (true, OperationArgs::U) => {
+            " The accumalator is used instead of register A, and the result is written to register A."
+        }
+        (true, OperationArgs::X) => {
+            " The accumalator is used instead of A, and only the accumalator and flags are set."
+        }
+    };
+    Some(format!("**{name}**\n\n`{usage}`\n\n{description}{args}"))
+}
+
+// Shows what a pseudo-instruction expands to, with example oprands
+fn pseudo_docs(name: &str, pseudo: Pseudo) -> String {
+    let (a, b) = pseudo.needed_oprands();
+    let example_b = match b {
+        Some(Oprand::Immediate(_)) => Oprand::Immediate(5),
+        _ => Oprand::Register(2),
+    };
+    let mut written = name.to_string();
+    if a.is_some() {
+        written += &format!(" {}", Oprand::Register(1));
+    }
+    if b.is_some() {
+        written += &format!(" {example_b}");
+    }
+    let expanded: Vec<String> = pseudo
+        .expand(Oprand::Register(1), example_b)
+        .iter()
+        .map(|instruction| format!("`{instruction}`"))
+        .collect();
+    format!(
+        "**{name}** (pseudo-instruction)\n\n`{written}` is assembled as {}",
+        expanded.join(", ")
+    )
+}
+
+fn symbol_docs(symbol: &Symbol) -> String {
+    let kind = match symbol.kind {
+        SymbolKind::Label => "label, address",
+        SymbolKind::Constant => "constant,",
+        SymbolKind::Alias => "alias of",
+    };
+    format!(
+        "**{}** ({kind} {})\n\nDefined on {}",
+        symbol.name, symbol.value, symbol.defined
+    )
+}
+
+// The semantic tokens of a line, as the column, length and index into
+// `TOKEN_TYPES` of each
+fn highlights(line: &SyntaxLine) -> Vec<(usize, usize, usize)> {
+    let token_type = |name: &str| TOKEN_TYPES.iter().position(|t| *t == name).unwrap();
+    let mut highlights = vec![];
+    for token in &line.tokens {
+        let whole = match token.kind {
+            TokenKind::Label => Some("function"),
+            TokenKind::Directive => Some("macro"),
+            TokenKind::Mnemonic if is_operation_name(&token.text.to_ascii_uppercase()) => {
+                Some("keyword")
+            }
+            // Macro calls
+            TokenKind::Mnemonic => Some("macro"),
+            TokenKind::Comment => Some("comment"),
+            _ => None,
+        };
+        if let Some(name) = whole {
+            highlights.push((token.column, token.text.len(), token_type(name)));
+            continue;
+        }
+        if token.kind != TokenKind::Oprand {
+            continue;
+        }
+        // Oprands are highlighted a word at a time, as they can be expressions
+        let mut word_start = None;
+        let chars = token.text.char_indices().chain([(token.text.len(), ' ')]);
+        for (index, char) in chars {
+            let in_word = char.is_ascii_alphanumeric() || matches!(char, '_' | '$' | '\'');
+            match (in_word, word_start) {
+                (true, None) => word_start = Some(index),
+                (false, Some(start)) => {
+                    let word = &token.text[start..index];
+                    let name = oprand_word_type(&token.text[..start], word);
+                    highlights.push((token.column + start, word.len(), token_type(name)));
+                    word_start = None;
+                }
+                _ => (),
+            }
+            if !in_word && "+-*/%&|^~<>".contains(char) {
+                highlights.push((token.column + index, 1, token_type("operator")));
+            }
+        }
+    }
+    highlights
+}
+
+// The kind of a word in an oprand, given what comes before it
+fn oprand_word_type(before: &str, word: &str) -> &'static str {
+    let upper = word.to_ascii_uppercase();
+    if word.starts_with('\'') {
+        "string"
+    } else if before.ends_with(['%', '#']) && before.len() == 1 {
+        "parameter"
+    } else if upper
+        .strip_prefix('R')
+        .is_some_and(crate::expression::is_number_literal)
+    {
+        "variable"
+    } else if crate::expression::is_number_literal(&upper) {
+        "number"
+    } else {
+        "function"
+    }
+}
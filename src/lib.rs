@@ -0,0 +1,20 @@
+pub mod control_flow;
+pub mod dataflow;
+pub mod diagnostic;
+pub mod disassembler;
+pub mod emulator;
+pub mod encoding;
+pub mod expression;
+pub mod formatter;
+pub mod hazards;
+pub mod isa;
+pub mod language_server;
+pub mod listing;
+pub mod machine;
+pub mod nbt;
+pub mod parser;
+mod preprocessor;
+mod pseudo;
+pub mod schedule;
+pub mod schematic;
+pub mod syntax;
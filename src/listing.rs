@@ -0,0 +1,95 @@
+use crate::hazards::*;
+use crate::parser::*;
+
+// Listings show what each line of a program assembled to:
+//
+// ADDR  BYTES     LINE  INSTRUCTION       SOURCE
+//   00  10 01 01     3  IMM R1 1          IMM R1 ONE
+//   01  33 01 01     4  SADD R1 R1        loop: SADD R1 R1
+//   02  60 00 02     5  NOT R0 R2         SUB R1 R2
+//   03  48 01 01     5  UADDC R1 R1
+//   04  80 01 00     6  JMP 1             JMP loop
+//   05  70 00 01     7  OUT %0 R1         OUT %0 R1  (delay slot)
+//
+// Instructions that run after a branch before it takes effect are marked as
+// being in its delay slot. The listing ends with a table of every label,
+// constant and alias.
+
+// How wide the instruction column is
+const INSTRUCTION_WIDTH: usize = 18;
+
+impl SymbolKind {
+    fn name(&self) -> &'static str {
+        match self {
+            SymbolKind::Label => "label",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Alias => "alias",
+        }
+    }
+}
+
+impl Program {
+    /// Lists the address, bytes, source line and resolved instruction of every
+    /// instruction, then the program's symbols.
+    pub fn listing(&self) -> String {
+        let lines: Vec<String> = self
+            .instructions
+            .iter()
+            .map(|instruction| match &instruction.source {
+                Some(source) => source.to_string(),
+                None => String::new(),
+            })
+            .collect();
+        let line_width = lines.iter().map(String::len).max().unwrap_or(0).max(4);
+        let mut listing = format!(
+            "ADDR  BYTES     {:>line_width$}  {:<INSTRUCTION_WIDTH$}SOURCE\n",
+            "LINE", "INSTRUCTION"
+        );
+        for (address, instruction) in self.instructions.iter().enumerate() {
+            let bytes = instruction
+                .encode()
+                .map(|byte| format!("{byte:02X}"))
+                .join(" ");
+            let delay_slot =
+                shadowing_branch(&self.instructions, address, &Pipeline::EMULATOR).is_some();
+            let line = format!(
+                "  {address:02}  {bytes}  {:>line_width$}  {:<INSTRUCTION_WIDTH$}{}{}",
+                lines[address],
+                instruction.to_string(),
+                match &instruction.source {
+                    // The rest of a pseudo-instruction's expansion is listed
+                    // under it
+                    Some(source) if source.part == 0 => source.text.trim(),
+                    _ => "",
+                },
+                if delay_slot { "  (delay slot)" } else { "" }
+            );
+            listing += line.trim_end();
+            listing += "\n";
+        }
+        if self.symbols.is_empty() {
+            return listing;
+        }
+        let name_width = self
+            .symbols
+            .iter()
+            .map(|symbol| symbol.name.len())
+            .max()
+            .unwrap_or(0)
+            .max(6);
+        listing += &format!(
+            "\n{:<name_width$}  {:<8}  {:<5}  DEFINED\n",
+            "SYMBOL", "KIND", "VALUE"
+        );
+        for symbol in &self.symbols {
+            listing += &format!(
+                "{:<name_width$}  {:<8}  {:<5}  {}\n",
+                symbol.name,
+                symbol.kind.name(),
+                symbol.value,
+                symbol.defined
+            );
+        }
+        listing
+    }
+}
@@ -0,0 +1,128 @@
+use crate::parser::Instruction;
+use serde::Deserialize;
+
+// The sizes of the parts of a computer, so programs can be assembled and run
+// for hardware that hasn't been built yet. Electron V1 is the default, and
+// other machines are described in TOML files or picked by name:
+//
+// name = "Electron V2"
+// rom_lines = 64
+// registers = 16
+//
+// Anything left out is the same as Electron V1. Oprands are stored in one byte
+// of each instruction, so nothing can be numbered past 255 and words are at
+// most 8 bits.
+
+/// The sizes of the parts of a machine.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Machine {
+    pub name: String,
+    /// How many lines of ROM there are. The program counter goes back to line
+    /// 0 after the last one.
+    pub rom_lines: usize,
+    pub registers: usize,
+    pub output_ports: usize,
+    pub input_ports: usize,
+    /// How many bytes of memory there are
+    pub ram_size: usize,
+    /// How many bits registers, ports and the ALU hold
+    pub word_bits: u32,
+    /// The width and height of the emulator's window, in pixels
+    pub window: [i32; 2],
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self {
+            name: "Electron V1".to_string(),
+            rom_lines: 32,
+            registers: 8,
+            output_ports: 8,
+            input_ports: 8,
+            ram_size: 32,
+            word_bits: 8,
+            window: [720, 720],
+        }
+    }
+}
+
+// Machines that can be picked by name
+const NAMED: [&str; 1] = ["electron-v1"];
+
+impl Machine {
+    /// A machine that comes with the assembler, like "electron-v1".
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "electron-v1" => Some(Self::default()),
+            _ => None,
+        }
+    }
+
+    /// Reads a machine from TOML. Anything left out keeps its Electron V1 value.
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
+        let machine: Self = toml::from_str(toml).map_err(|error| error.to_string())?;
+        machine.check()?;
+        Ok(machine)
+    }
+
+    /// Picks the machine called `name`, or reads it from the TOML file `name`.
+    pub fn load(name: &str) -> Result<Self, String> {
+        if let Some(machine) = Self::by_name(name) {
+            return Ok(machine);
+        }
+        let toml = std::fs::read_to_string(name).map_err(|error| {
+            format!(
+                "\"{name}\" is not a machine ({}) and could not be read: {error}",
+                NAMED.join(", ")
+            )
+        })?;
+        Self::from_toml(&toml).map_err(|error| format!("In {name}: {error}"))
+    }
+
+    // Checks that everything fits in the instructions and the emulator
+    fn check(&self) -> Result<(), String> {
+        let counts = [
+            ("rom_lines", self.rom_lines),
+            ("registers", self.registers),
+            ("output_ports", self.output_ports),
+            ("input_ports", self.input_ports),
+            ("ram_size", self.ram_size),
+        ];
+        for (name, count) in counts {
+            if !(1..=256).contains(&count) {
+                return Err(format!("{name} is {count}, but it must be from 1 to 256"));
+            }
+        }
+        // The register checks keep a bit for each register
+        if self.registers > 128 {
+            return Err(format!(
+                "registers is {}, but at most 128 are supported",
+                self.registers
+            ));
+        }
+        if !(1..=8).contains(&self.word_bits) {
+            return Err(format!(
+                "word_bits is {}, but it must be from 1 to 8",
+                self.word_bits
+            ));
+        }
+        if self.window.iter().any(|size| *size <= 0) {
+            return Err(format!(
+                "window is {:?}, but it must be bigger",
+                self.window
+            ));
+        }
+        Ok(())
+    }
+
+    /// The biggest number a word holds.
+    pub fn max_word(&self) -> u8 {
+        (u16::MAX >> (16 - self.word_bits)) as u8
+    }
+
+    /// The number of bytes in ROM.
+    pub fn rom_bytes(&self) -> usize {
+        self.rom_lines * Instruction::SIZE
+    }
+}
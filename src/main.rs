@@ -1,12 +1,11 @@
-use emulator::*;
-use parser::*;
+use electron::disassembler::*;
+use electron::emulator::*;
+use electron::formatter::format;
+use electron::machine::Machine;
+use electron::parser::*;
+use electron::schematic::*;
 use raylib::prelude::*;
 
-mod emulator;
-mod parser;
-
-const WINDOW_SIZE: (i32, i32) = (720, 720);
-
 fn format_data(mut data: String, len: usize) -> String {
     for _ in 0..len - data.len() {
         data.push(' ')
@@ -15,8 +14,13 @@ fn format_data(mut data: String, len: usize) -> String {
 }
 
 fn print_port(emulator: &Emulator, port: u8) {
+    // Machines with fewer ports leave the rest of the line empty
+    if port as usize >= emulator.ports.out.len() {
+        println!();
+        return;
+    }
     let mut port_data = format!("{:b}", emulator.ports.out[port as usize]);
-    for _ in 0..8 - port_data.len() {
+    for _ in 0..emulator.machine().word_bits as usize - port_data.len() {
         port_data.insert(0, '0');
     }
     print!(
@@ -77,19 +81,27 @@ fn draw_terminal_screen(emulator: &Emulator) {
         "|             | Overflow: {}           |",
         format_data(emulator.alu.flags.less_than.to_string(), 5)
     );
+    for port in 8..emulator.ports.out.len() {
+        print!("{}", format_data(String::new(), 43));
+        print_port(emulator, port as u8);
+    }
     println!();
     println!("__________________________________________");
     println!();
     println!("▓▓▓▒▒▒░░░         Memory         ░░░▒▒▒▓▓▓");
     println!("__________________________________________");
     println!("| Registers |");
-    for i in 0..8 { 
+    for i in 0..emulator.machine().registers as u8 {
         println!(
             "|   {}: {}  |",
             i,
             format_data(emulator.registers.read(i).to_string(), 3)
         );
     }
+    if let Some(source) = &emulator.execute_register.source {
+        println!();
+        println!("Executing line {source}: {}", source.text.trim());
+    }
 }
 
 fn clear_terminal_screen() {
@@ -97,9 +109,14 @@ fn clear_terminal_screen() {
 }
 
 fn draw_ports(emulator: &Emulator, d: &mut RaylibDrawHandle, on_texture: &Texture2D, off_texture: &Texture2D) {
+    // Each port is a row of the window, with a square for each bit
+    let machine = emulator.machine();
+    let bits = machine.word_bits as i32;
+    let ports = machine.output_ports as i32;
+    let [width, height] = machine.window;
     for (port, _) in emulator.ports.out.iter().enumerate() {
         let mut port_data = format!("{:b}", emulator.ports.out[port]);
-        for _ in 0..8 - port_data.len() {
+        for _ in 0..bits as usize - port_data.len() {
             port_data.insert(0, '0');
         }
         for (i, char) in port_data.char_indices() {
@@ -108,10 +125,10 @@ fn draw_ports(emulator: &Emulator, d: &mut RaylibDrawHandle, on_texture: &Textur
                     on_texture,
                     Rectangle::new(0.0, 0.0, on_texture.width as f32, on_texture.height as f32),
                     Rectangle::new(
-                    (i as i32 * WINDOW_SIZE.0 / 8) as f32,
-                    (port as i32 * WINDOW_SIZE.1 / 8) as f32,
-                    (WINDOW_SIZE.0 / 8) as f32,
-                    (WINDOW_SIZE.1 / 8) as f32,
+                    (i as i32 * width / bits) as f32,
+                    (port as i32 * height / ports) as f32,
+                    (width / bits) as f32,
+                    (height / ports) as f32,
                     ),
                     Vector2::zero(),
                     0.0,
@@ -122,10 +139,10 @@ fn draw_ports(emulator: &Emulator, d: &mut RaylibDrawHandle, on_texture: &Textur
                     off_texture,
                     Rectangle::new(0.0, 0.0, off_texture.width as f32, off_texture.height as f32),
                     Rectangle::new(
-                    (i as i32 * WINDOW_SIZE.0 / 8) as f32,
-                    (port as i32 * WINDOW_SIZE.1 / 8) as f32,
-                    (WINDOW_SIZE.0 / 8) as f32,
-                    (WINDOW_SIZE.1 / 8) as f32,
+                    (i as i32 * width / bits) as f32,
+                    (port as i32 * height / ports) as f32,
+                    (width / bits) as f32,
+                    (height / ports) as f32,
                     ),
                     Vector2::zero(),
                     0.0,
@@ -136,8 +153,216 @@ fn draw_ports(emulator: &Emulator, d: &mut RaylibDrawHandle, on_texture: &Textur
     }
 }
 
+// Reads the machine given with "--machine name" or "--machine machine.toml",
+// or Electron V1
+fn machine_or_exit(args: &[String]) -> Machine {
+    match flag_value(args, "--machine") {
+        Some(name) => Machine::load(&name).unwrap_or_else(|error| exit_with_error(error)),
+        None => Machine::default(),
+    }
+}
+
+// Assembles a file, printing its warnings, or its errors before exiting
+fn assemble_or_exit(file_name: &str, args: &[String]) -> Program {
+    let options = AssemblyOptions {
+        schedule: args.iter().any(|arg| arg == "--schedule"),
+        fill_delay_slots: args.iter().any(|arg| arg == "--fill-delay-slots"),
+        machine: machine_or_exit(args),
+    };
+    match ProgramLoader::load_program_with(file_name, &options) {
+        Ok(program) => {
+            for warning in &program.warnings {
+                println!("{}", warning.render(file_name));
+            }
+            program
+        }
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                println!("{}", diagnostic.render(file_name));
+            }
+            println!("Failed to compile {file_name}.");
+            std::process::exit(1)
+        }
+    }
+}
+
+fn exit_with_error(message: impl std::fmt::Display) -> ! {
+    println!("Error: {message}");
+    std::process::exit(1)
+}
+
+// The value given after a flag like "-o"
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    Some(args.get(i + 1).cloned().unwrap_or_default())
+}
+
+fn with_extension(file_name: &str, extension: &str) -> String {
+    std::path::Path::new(file_name)
+        .with_extension(extension)
+        .to_string_lossy()
+        .to_string()
+}
+
+// electron assemble program.elt [-o program.bin] [--listing program.lst] [--cfg program.dot]
+//     [--schedule] [--fill-delay-slots] [--machine electron-v1]
+fn assemble_command(args: &[String]) {
+    let Some(file_name) = args.first() else {
+        exit_with_error(
+            "No file name given. Usage: \"assemble your_file_name [-o output] [--listing listing] [--cfg graph]\".",
+        );
+    };
+    let output = flag_value(args, "-o").unwrap_or_else(|| with_extension(file_name, "bin"));
+    let program = assemble_or_exit(file_name, args);
+    let rom = program.to_rom().unwrap_or_else(|error| exit_with_error(error));
+    let rom_bytes = rom.len();
+    if let Err(error) = std::fs::write(&output, rom) {
+        exit_with_error(format!("Could not write {output}: {error}"));
+    }
+    println!("Wrote {rom_bytes} byte ROM image to {output}.");
+    if let Some(listing) = flag_value(args, "--listing") {
+        let listing = match listing.as_str() {
+            "" => with_extension(file_name, "lst"),
+            _ => listing,
+        };
+        if let Err(error) = std::fs::write(&listing, program.listing()) {
+            exit_with_error(format!("Could not write {listing}: {error}"));
+        }
+        println!("Wrote listing to {listing}.");
+    }
+    if let Some(dot) = flag_value(args, "--cfg") {
+        let dot = match dot.as_str() {
+            "" => with_extension(file_name, "dot"),
+            _ => dot,
+        };
+        let graph = program.control_flow_graph();
+        if let Err(error) = std::fs::write(&dot, graph.to_dot(&program.instructions)) {
+            exit_with_error(format!("Could not write {dot}: {error}"));
+        }
+        println!("Wrote control-flow graph to {dot}.");
+    }
+}
+
+// electron disassemble program.bin [-o program.elt]
+fn disassemble_command(args: &[String]) {
+    let Some(file_name) = args.first() else {
+        exit_with_error("No file name given. Usage: \"disassemble your_rom [-o output]\".");
+    };
+    let rom = std::fs::read(file_name)
+        .unwrap_or_else(|error| exit_with_error(format!("Could not read {file_name}: {error}")));
+    let source = disassemble(&rom).unwrap_or_else(|error| exit_with_error(error));
+    write_or_print(args, &source);
+}
+
+// Writes to the file given with "-o", or prints if there isn't one
+fn write_or_print(args: &[String], text: &str) {
+    match flag_value(args, "-o") {
+        Some(output) => {
+            if let Err(error) = std::fs::write(&output, text) {
+                exit_with_error(format!("Could not write {output}: {error}"));
+            }
+        }
+        None => print!("{text}"),
+    }
+}
+
+// Reads the ROM layout given with "-l layout.toml", or the default one
+fn layout_or_exit(args: &[String]) -> RomLayout {
+    let Some(layout_file) = flag_value(args, "-l") else {
+        return RomLayout::default();
+    };
+    let toml = std::fs::read_to_string(&layout_file)
+        .unwrap_or_else(|error| exit_with_error(format!("Could not read {layout_file}: {error}")));
+    RomLayout::from_toml(&toml)
+        .unwrap_or_else(|error| exit_with_error(format!("In {layout_file}: {error}")))
+}
+
+// electron schematic program.elt [-o program.schem] [-l layout.toml] [--schedule]
+//     [--fill-delay-slots] [--machine electron-v1]
+fn schematic_command(args: &[String]) {
+    let Some(file_name) = args.first() else {
+        exit_with_error(
+            "No file name given. Usage: \"schematic your_file_name [-o output] [-l layout]\".",
+        );
+    };
+    let output = flag_value(args, "-o").unwrap_or_else(|| with_extension(file_name, "schem"));
+    let layout = layout_or_exit(args);
+    let program = assemble_or_exit(file_name, args);
+    let mut schematic = vec![];
+    if let Err(error) = write_schematic(&program, &layout, &mut schematic) {
+        exit_with_error(error);
+    }
+    if let Err(error) = std::fs::write(&output, schematic) {
+        exit_with_error(format!("Could not write {output}: {error}"));
+    }
+    println!("Wrote schematic of the ROM to {output}.");
+}
+
+// electron fmt program.elt... [--check]
+fn fmt_command(args: &[String]) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if files.is_empty() {
+        exit_with_error("No file name given. Usage: \"fmt your_file_name... [--check]\".");
+    }
+    let mut unformatted = false;
+    for file_name in files {
+        let source = std::fs::read_to_string(file_name)
+            .unwrap_or_else(|error| exit_with_error(format!("Could not read {file_name}: {error}")));
+        let formatted = format(&source);
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{file_name} is not formatted.");
+            unformatted = true;
+        } else if let Err(error) = std::fs::write(file_name, formatted) {
+            exit_with_error(format!("Could not write {file_name}: {error}"));
+        } else {
+            println!("Formatted {file_name}.");
+        }
+    }
+    if unformatted {
+        std::process::exit(1);
+    }
+}
+
+// electron import rom.schem [-l layout.toml] [-o program.elt] [--machine electron-v1]
+fn import_command(args: &[String]) {
+    let Some(file_name) = args.first() else {
+        exit_with_error(
+            "No file name given. Usage: \"import your_schematic [-l layout] [-o output]\".",
+        );
+    };
+    let layout = layout_or_exit(args);
+    let machine = machine_or_exit(args);
+    let file = std::fs::File::open(file_name)
+        .unwrap_or_else(|error| exit_with_error(format!("Could not read {file_name}: {error}")));
+    let rom = read_rom(std::io::BufReader::new(file), &layout, &machine)
+        .unwrap_or_else(|error| exit_with_error(error));
+    let source = disassemble(&rom).unwrap_or_else(|error| exit_with_error(error));
+    write_or_print(args, &source);
+}
+
+// electron lsp, started by editors rather than people
+fn lsp_command() {
+    let stdin = std::io::stdin();
+    if let Err(error) = electron::language_server::run(stdin.lock(), std::io::stdout()) {
+        exit_with_error(format!("Language server stopped: {error}"));
+    }
+}
+
 fn main() {
     let args = std::env::args().collect::<Vec<String>>();
+    match args.get(1).map(String::as_str) {
+        Some("assemble") => return assemble_command(&args[2..]),
+        Some("disassemble") => return disassemble_command(&args[2..]),
+        Some("schematic") => return schematic_command(&args[2..]),
+        Some("import") => return import_command(&args[2..]),
+        Some("fmt") => return fmt_command(&args[2..]),
+        Some("lsp") => return lsp_command(),
+        _ => (),
+    }
     let mut file_name = String::new();
     let mut terminal_output = true;
     let mut clock_speed = 1.0;
@@ -156,11 +381,18 @@ fn main() {
             show_fps = true;
         }
     }
-    let program = ProgramLoader::load_program(&file_name);
+    if file_name.is_empty() {
+        println!("Error: No file name given. This argument is required: \"-f your_file_name\".");
+        std::process::exit(1);
+    }
+    let program = assemble_or_exit(&file_name, &args);
+    print!("{}", program.listing());
+    println!("Successfully validated program.");
+    let [width, height] = program.machine.window;
     let (mut rl, thread) = raylib::init()
-        .width(WINDOW_SIZE.0)
+        .width(width)
         .title("Electron Emulator")
-        .height(WINDOW_SIZE.1)
+        .height(height)
         .build();
     let mut emulator = Emulator::new(program);
     let mut last_clock = std::time::Instant::now();
@@ -0,0 +1,205 @@
+use std::io::{self, Read, Write};
+
+// Minecraft's Named Binary Tag format, which schematics are saved in. Numbers
+// are big endian and strings are prefixed with their length. Only what
+// schematics need is supported, so strings are written as plain UTF-8.
+
+// How deeply lists and compounds can be nested, the same limit as Minecraft's,
+// so a malicious file can't overflow the stack
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Finds the tag called `name` in a compound.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags
+                .iter()
+                .find(|(tag_name, _)| tag_name == name)
+                .map(|(_, tag)| tag),
+            _ => None,
+        }
+    }
+
+    fn write_payload(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Tag::Byte(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Short(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Int(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Long(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Float(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::Double(value) => writer.write_all(&value.to_be_bytes()),
+            Tag::ByteArray(values) => {
+                write_length(writer, values.len())?;
+                let bytes: Vec<u8> = values.iter().map(|value| *value as u8).collect();
+                writer.write_all(&bytes)
+            }
+            Tag::String(value) => write_string(writer, value),
+            Tag::List(tags) => {
+                // Every tag in a list must be the same type, empty lists are lists of End
+                let id = tags.first().map_or(0, Tag::id);
+                if tags.iter().any(|tag| tag.id() != id) {
+                    return Err(invalid_data("Lists can only hold one type of tag"));
+                }
+                writer.write_all(&[id])?;
+                write_length(writer, tags.len())?;
+                tags.iter().try_for_each(|tag| tag.write_payload(writer))
+            }
+            Tag::Compound(tags) => {
+                for (name, tag) in tags {
+                    tag.write(name, writer)?;
+                }
+                writer.write_all(&[0])
+            }
+            Tag::IntArray(values) => {
+                write_length(writer, values.len())?;
+                values
+                    .iter()
+                    .try_for_each(|value| writer.write_all(&value.to_be_bytes()))
+            }
+            Tag::LongArray(values) => {
+                write_length(writer, values.len())?;
+                values
+                    .iter()
+                    .try_for_each(|value| writer.write_all(&value.to_be_bytes()))
+            }
+        }
+    }
+
+    /// Writes the tag with its name, as the root of a file or part of a compound.
+    pub fn write(&self, name: &str, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&[self.id()])?;
+        write_string(writer, name)?;
+        self.write_payload(writer)
+    }
+
+    /// Reads a named tag written by `write`, returning its name and the tag.
+    pub fn read(reader: &mut impl Read) -> io::Result<(String, Tag)> {
+        let id = read_array::<1>(reader)?[0];
+        if id == 0 {
+            return Err(invalid_data("Expected a tag, found the end of a compound"));
+        }
+        let name = read_string(reader)?;
+        Ok((name, Self::read_payload(id, reader, 0)?))
+    }
+
+    // Reads the payload of a tag that is inside `depth` lists and compounds
+    fn read_payload(id: u8, reader: &mut impl Read, depth: usize) -> io::Result<Tag> {
+        if matches!(id, 9 | 10) && depth >= MAX_DEPTH {
+            return Err(invalid_data(&format!(
+                "Tags are nested more than {MAX_DEPTH} levels deep"
+            )));
+        }
+        Ok(match id {
+            1 => Tag::Byte(i8::from_be_bytes(read_array(reader)?)),
+            2 => Tag::Short(i16::from_be_bytes(read_array(reader)?)),
+            3 => Tag::Int(i32::from_be_bytes(read_array(reader)?)),
+            4 => Tag::Long(i64::from_be_bytes(read_array(reader)?)),
+            5 => Tag::Float(f32::from_be_bytes(read_array(reader)?)),
+            6 => Tag::Double(f64::from_be_bytes(read_array(reader)?)),
+            7 => {
+                let length = read_length(reader)?;
+                let mut bytes = vec![];
+                reader.take(length as u64).read_to_end(&mut bytes)?;
+                if bytes.len() != length {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Tag::ByteArray(bytes.into_iter().map(|byte| byte as i8).collect())
+            }
+            8 => Tag::String(read_string(reader)?),
+            9 => {
+                let id = read_array::<1>(reader)?[0];
+                let length = read_length(reader)?;
+                let tags = (0..length).map(|_| Self::read_payload(id, reader, depth + 1));
+                Tag::List(tags.collect::<io::Result<_>>()?)
+            }
+            10 => {
+                let mut tags = vec![];
+                loop {
+                    let id = read_array::<1>(reader)?[0];
+                    if id == 0 {
+                        break Tag::Compound(tags);
+                    }
+                    let name = read_string(reader)?;
+                    tags.push((name, Self::read_payload(id, reader, depth + 1)?));
+                }
+            }
+            11 => {
+                let length = read_length(reader)?;
+                let values = (0..length).map(|_| Ok(i32::from_be_bytes(read_array(reader)?)));
+                Tag::IntArray(values.collect::<io::Result<_>>()?)
+            }
+            12 => {
+                let length = read_length(reader)?;
+                let values = (0..length).map(|_| Ok(i64::from_be_bytes(read_array(reader)?)));
+                Tag::LongArray(values.collect::<io::Result<_>>()?)
+            }
+            _ => return Err(invalid_data(&format!("{id} is not a valid tag type"))),
+        })
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_length(writer: &mut impl Write, length: usize) -> io::Result<()> {
+    let length = i32::try_from(length).map_err(|_| invalid_data("Array is too long"))?;
+    writer.write_all(&length.to_be_bytes())
+}
+
+fn write_string(writer: &mut impl Write, string: &str) -> io::Result<()> {
+    let length = u16::try_from(string.len()).map_err(|_| invalid_data("String is too long"))?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(string.as_bytes())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_length(reader: &mut impl Read) -> io::Result<usize> {
+    usize::try_from(i32::from_be_bytes(read_array(reader)?))
+        .map_err(|_| invalid_data("Array has a negative length"))
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = u16::from_be_bytes(read_array(reader)?);
+    let mut bytes = vec![0; length as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid_data("String is not valid UTF-8"))
+}
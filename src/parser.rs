@@ -1,68 +1,468 @@
+use crate::control_flow::*;
+use crate::dataflow::*;
+use crate::diagnostic::*;
+use crate::expression::*;
+use crate::hazards::*;
+use crate::isa::*;
+use crate::machine::Machine;
+use crate::preprocessor::*;
+use crate::pseudo::Pseudo;
+use crate::schedule::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+
 pub struct ProgramLoader;
 
+/// Settings for how a program is assembled.
+#[derive(Debug, Clone, Default)]
+pub struct AssemblyOptions {
+    /// Add NOOPs and reorder instructions so the program runs as if each
+    /// instruction finished before the next one started
+    pub schedule: bool,
+    /// Fill the delay slots of branches followed by instructions with side
+    /// effects, so those instructions don't run before the branch is taken
+    pub fill_delay_slots: bool,
+    /// The machine the program is for, which decides how big it can be and
+    /// which registers and ports exist
+    pub machine: Machine,
+}
+
 #[derive(Debug)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
+    pub warnings: Vec<Diagnostic>,
+    /// Every label, constant and alias, sorted by name
+    pub symbols: Vec<Symbol>,
+    /// The machine it was assembled for
+    pub machine: Machine,
+}
+
+/// The line an instruction was written on. Lines are counted from 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    /// The included file the line is in, or `None` for the file being assembled
+    pub file: Option<String>,
+    pub line: usize,
+    /// The line's text, after any macro was expanded
+    pub text: String,
+    /// Which of the instructions a pseudo-instruction expanded to this is,
+    /// counting from 0
+    pub part: usize,
+}
+
+impl SourceLocation {
+    fn new(line: &SourceLine, part: usize) -> Self {
+        Self {
+            file: line.file.clone(),
+            line: line.line,
+            text: line.text.clone(),
+            part,
+        }
+    }
+}
+
+// Writes the line number, with the file it is in if it was included, like
+// "display.elt:3"
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{file}:{}", self.line + 1),
+            None => write!(f, "{}", self.line + 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Label,
+    Constant,
+    Alias,
+}
+
+/// A name defined by a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The address or value it stands for, or the oprand it is another name for
+    pub value: String,
+    /// Where it was defined, like "line 3 of display.elt"
+    pub defined: String,
+}
+
+// Where a word was found on its line
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Span {
+    pub column: usize,
+    pub length: usize,
+}
+
+// An error found while parsing a line, before its line number is known
+pub(crate) struct LineError {
+    span: Span,
+    message: String,
+    help: Option<String>,
+}
+
+impl LineError {
+    fn new(span: Span, message: String) -> Self {
+        Self {
+            span,
+            message,
+            help: None,
+        }
+    }
+
+    fn into_diagnostic(self, line: &SourceLine) -> Diagnostic {
+        line.error(self.span, self.message).with_help(self.help)
+    }
+}
+
+// An instruction as it was written, before pseudo-instructions are expanded
+struct WrittenInstruction {
+    name: String,
+    needed_oprands: (Option<Oprand>, Option<Oprand>),
+    a: Oprand,
+    b: Oprand,
+    // Where the instruction and its oprands were found on the line
+    operation: Span,
+    oprands: (Span, Span),
+}
+
+// Where an instruction was found in the source
+struct InstructionSource<'a> {
+    line: &'a SourceLine,
+    written: WrittenInstruction,
+    // The addresses of the instructions it assembled to
+    addresses: Range<usize>,
+}
+
+// Where an .alias was defined
+struct AliasSource<'a> {
+    name: String,
+    line: &'a SourceLine,
+    span: Span,
+}
+
+// Names defined by a program
+#[derive(Default)]
+struct Symbols {
+    // Labels and .equ constants, which can be used in expressions
+    constants: HashMap<String, i64>,
+    // Oprands given another name with .alias
+    aliases: HashMap<String, Oprand>,
+    // Where each name was defined, like "line 3 of display.elt"
+    lines: HashMap<String, String>,
+    // Which constants are labels
+    labels: HashSet<String>,
+}
+
+impl Symbols {
+    fn define_constant(
+        &mut self,
+        name: String,
+        value: i64,
+        line: &SourceLine,
+        span: Span,
+    ) -> Result<(), LineError> {
+        if let Some(defined_on) = self.lines.get(&name) {
+            let message = format!("\"{name}\" is already defined on {defined_on}");
+            return Err(LineError::new(span, message));
+        }
+        self.lines.insert(name.clone(), line.describe());
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    fn define_equ(&mut self, directive: Directive, line: &SourceLine) -> Result<(), LineError> {
+        let name = directive.symbol;
+        if !is_label_name(&name) {
+            let message = format!("\"{name}\" is not a valid constant name");
+            return Err(LineError::new(directive.symbol_span, message));
+        }
+        let value = evaluate_oprand(&directive.value, self)
+            .map_err(|message| LineError::new(directive.value_span, message))?;
+        self.define_constant(name, value, line, directive.symbol_span)
+    }
+
+    fn define_alias(&mut self, directive: &Directive, machine: &Machine) -> Result<(), LineError> {
+        let name = &directive.symbol;
+        if !is_label_name(name) && !is_register_name(name) {
+            let message = format!("\"{name}\" is not a valid alias name");
+            return Err(LineError::new(directive.symbol_span, message));
+        }
+        if let Some(defined_on) = self.lines.get(name) {
+            let message = format!("\"{name}\" is already defined on {defined_on}");
+            return Err(LineError::new(directive.symbol_span, message));
+        }
+        let oprand = parse_oprand(&directive.value, self, machine, parse_word)
+            .map_err(|message| LineError::new(directive.value_span, message))?;
+        self.aliases.insert(name.clone(), oprand);
+        Ok(())
+    }
+
+    // Lists every name for the program, with aliases as they were at the end
+    fn table(&self, aliases: &[AliasSource]) -> Vec<Symbol> {
+        let mut table: Vec<Symbol> = self
+            .constants
+            .iter()
+            .map(|(name, value)| Symbol {
+                name: name.clone(),
+                kind: if self.labels.contains(name) {
+                    SymbolKind::Label
+                } else {
+                    SymbolKind::Constant
+                },
+                value: value.to_string(),
+                defined: self.lines[name].clone(),
+            })
+            .collect();
+        for (name, oprand) in &self.aliases {
+            let Some(alias) = aliases.iter().rev().find(|alias| &alias.name == name) else {
+                continue;
+            };
+            table.push(Symbol {
+                name: name.clone(),
+                kind: SymbolKind::Alias,
+                value: oprand.to_string(),
+                defined: alias.line.describe(),
+            });
+        }
+        table.sort_by(|a, b| a.name.cmp(&b.name));
+        table
+    }
+
+    // Suggests the name a misspelled one was most likely meant to be
+    fn suggest(&self, name: &str) -> Option<String> {
+        let names = self.constants.keys().chain(self.aliases.keys());
+        closest_match(name, names.map(String::as_str)).map(|name| format!("did you mean {name}?"))
+    }
+}
+
+// An assembler directive, like ".equ WIDTH 8" or ".alias counter R3"
+struct Directive {
+    name: String,
+    symbol: String,
+    symbol_span: Span,
+    value: String,
+    value_span: Span,
 }
 
 impl ProgramLoader {
-    pub fn load_program(file_name: &str) -> Program {
-        if file_name.is_empty() {
-            println!("Error: No file name given. This argument is required: \"-f your_file_name\".");
-            std::process::exit(1);
-        }
-        let mut file = match std::fs::File::open(file_name) {
-            Ok(file) => file,
-            Err(_) => {
-                println!("Error: Could not find file specified. {file_name}");
-                std::process::exit(1);
-            },
-        };
+    /// Assembles the program in the file `file_name`.
+    pub fn load_program(file_name: &str) -> Result<Program, Vec<Diagnostic>> {
+        Self::load_program_with(file_name, &AssemblyOptions::default())
+    }
+
+    /// Assembles the program in the file `file_name` with `options`.
+    pub fn load_program_with(
+        file_name: &str,
+        options: &AssemblyOptions,
+    ) -> Result<Program, Vec<Diagnostic>> {
+        match std::fs::read_to_string(file_name) {
+            Ok(source) => Self::assemble_file_with(file_name, &source, options),
+            Err(error) => Err(vec![Diagnostic::error(
+                0,
+                0,
+                format!("Could not open {file_name}: {error}"),
+            )]),
+        }
+    }
+
+    /// Assembles the program read from `reader`.
+    pub fn from_reader(mut reader: impl Read) -> Result<Program, Vec<Diagnostic>> {
         let mut buffer = String::new();
-        std::io::Read::read_to_string(&mut file, &mut buffer).unwrap();
-        let mut errors: Vec<CompileMessage> = vec![];
-        let mut warnings: Vec<CompileMessage> = vec![];
+        match reader.read_to_string(&mut buffer) {
+            Ok(_) => Self::assemble(&buffer),
+            Err(error) => Err(vec![Diagnostic::error(
+                0,
+                0,
+                format!("Could not read program: {error}"),
+            )]),
+        }
+    }
+
+    /// Assembles a program from its source text. Warnings are returned with
+    /// the program, or alongside the errors if the program failed to assemble.
+    /// Included files are found relative to the current directory.
+    pub fn assemble(source: &str) -> Result<Program, Vec<Diagnostic>> {
+        Self::assemble_at(source, None, &AssemblyOptions::default())
+    }
+
+    /// Assembles `source`, read from the file `file_name`, finding included
+    /// files relative to it.
+    pub fn assemble_file(file_name: &str, source: &str) -> Result<Program, Vec<Diagnostic>> {
+        Self::assemble_file_with(file_name, source, &AssemblyOptions::default())
+    }
+
+    /// Assembles `source`, read from the file `file_name`, with `options`.
+    pub fn assemble_file_with(
+        file_name: &str,
+        source: &str,
+        options: &AssemblyOptions,
+    ) -> Result<Program, Vec<Diagnostic>> {
+        Self::assemble_at(source, Some(Path::new(file_name)), options)
+    }
+
+    fn assemble_at(
+        source: &str,
+        path: Option<&Path>,
+        options: &AssemblyOptions,
+    ) -> Result<Program, Vec<Diagnostic>> {
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+        let lines = preprocess(source, path, &mut diagnostics);
         let mut instructions = vec![];
-        for (line_num, line) in buffer.lines().enumerate() {
-            if let Ok(instruction) = parse_line(line) {
-                instructions.push(instruction)
-            } else if let Err(error) = parse_line(line) {
-                errors.push(CompileMessage {
-                    line: line_num,
-                    message: error,
-                })
+        let mut sources = vec![];
+        let mut aliases = vec![];
+        let mut symbols = Symbols::default();
+        // First pass: find the address of every label and the value of every constant
+        let mut address: usize = 0;
+        for line in &lines {
+            let (label, rest) = match split_label(&line.text) {
+                Ok((label, rest)) => (label, rest),
+                Err(error) => {
+                    diagnostics.push(error.into_diagnostic(line));
+                    (None, 0)
+                }
+            };
+            if let Some((label, span)) = label {
+                match symbols.define_constant(label.clone(), address as i64, line, span) {
+                    Ok(()) => {
+                        symbols.labels.insert(label);
+                    }
+                    Err(error) => diagnostics.push(error.into_diagnostic(line)),
+                }
+            }
+            match parse_directive(&line.text, rest) {
+                Some(Ok(directive)) => {
+                    if directive.name == ".EQU" {
+                        if let Err(error) = symbols.define_equ(directive, line) {
+                            diagnostics.push(error.into_diagnostic(line));
+                        }
+                    }
+                    continue;
+                }
+                Some(Err(error)) => {
+                    diagnostics.push(error.into_diagnostic(line));
+                    continue;
+                }
+                None => (),
+            }
+            // Blank lines, comments and lines with only a label do not take up
+            // an instruction
+            let code = strip_comment(&line.text)[rest..].trim();
+            if code.is_empty() {
+                continue;
             }
+            let name = code.split_whitespace().next().unwrap_or_default();
+            address +=
+                Pseudo::from_name(&name.to_ascii_uppercase()).map_or(1, |pseudo| pseudo.size());
         }
-        add_warnings(&instructions, &mut warnings);
-        for warning in warnings {
-            println!("Warning on line {}: {}.", warning.line, warning.message);
+        // Second pass: parse instructions with every label known
+        for line in &lines {
+            let Ok((_, rest)) = split_label(&line.text) else {
+                continue;
+            };
+            match parse_directive(&line.text, rest) {
+                // Aliases apply from the line they are defined on
+                Some(Ok(directive)) if directive.name == ".ALIAS" => {
+                    match symbols.define_alias(&directive, &options.machine) {
+                        Ok(()) => aliases.push(AliasSource {
+                            name: directive.symbol,
+                            line,
+                            span: directive.symbol_span,
+                        }),
+                        Err(error) => diagnostics.push(error.into_diagnostic(line)),
+                    }
+                    continue;
+                }
+                // Other directives were handled in the first pass
+                Some(_) => continue,
+                None => (),
+            }
+            if strip_comment(&line.text)[rest..].trim().is_empty() {
+                continue;
+            }
+            match parse_line(&line.text, rest, &symbols, &options.machine) {
+                Ok((assembled, written)) => {
+                    let start = instructions.len();
+                    for (part, mut instruction) in assembled.into_iter().enumerate() {
+                        instruction.source = Some(SourceLocation::new(line, part));
+                        instructions.push(instruction);
+                    }
+                    sources.push(InstructionSource {
+                        line,
+                        written,
+                        addresses: start..instructions.len(),
+                    });
+                }
+                Err(error) => diagnostics.push(error.into_diagnostic(line)),
+            }
         }
-        // Successfull Validation
-        if errors.is_empty() {
-            for (line_num, instruction) in instructions.iter().enumerate() {
-                println!(
-                    "{line_num}:  {:?} {:?} {:?} {:?}",
-                    instruction.operation, instruction.operation_args, instruction.a, instruction.b
-                );
+        // The line each instruction was assembled from
+        let mut instruction_sources: Vec<Option<&InstructionSource>> = sources
+            .iter()
+            .flat_map(|source| source.addresses.clone().map(move |_| Some(source)))
+            .collect();
+        type Pass = fn(&[Instruction], &Pipeline, &Machine) -> Result<Schedule, ScheduleError>;
+        let passes: [(bool, Pass); 2] = [
+            (options.fill_delay_slots, fill_delay_slots),
+            (options.schedule, schedule),
+        ];
+        for (_, pass) in passes.iter().filter(|(enabled, _)| *enabled) {
+            if diagnostics.iter().any(Diagnostic::is_error) {
+                break;
             }
-            // Add empty lines if lines < 32
-            for _ in 0..32 - instructions.len().min(32) {
-                instructions.push(Instruction::none())
+            match pass(&instructions, &Pipeline::EMULATOR, &options.machine) {
+                Ok(schedule) => {
+                    instruction_sources = schedule
+                        .from
+                        .iter()
+                        .map(|from| from.and_then(|address| instruction_sources[address]))
+                        .collect();
+                    for label in &symbols.labels {
+                        if let Some(address) = symbols.constants.get_mut(label) {
+                            *address = schedule.relocate(*address as usize) as i64;
+                        }
+                    }
+                    instructions = schedule.instructions;
+                }
+                Err(error) => {
+                    let source = instruction_sources.get(error.address).copied().flatten();
+                    if let Some(source) = source.or(sources.last()) {
+                        let operation = source.written.operation;
+                        diagnostics.push(source.line.error(operation, error.message));
+                    }
+                }
             }
-            println!("Successfully validated program.");
-            Program { instructions }
-        // Failed to validate
+        }
+        add_warnings(
+            &instructions,
+            &sources,
+            &instruction_sources,
+            &aliases,
+            &options.machine,
+            &mut diagnostics,
+        );
+        diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            Err(diagnostics)
         } else {
-            for error in errors {
-                println!("Error on line {}: {}.", error.line, error.message);
-            }
-            println!("Failed to compile {file_name}.");
-            std::process::exit(1)
+            Ok(Program {
+                symbols: symbols.table(&aliases),
+                instructions,
+                warnings: diagnostics,
+                machine: options.machine.clone(),
+            })
         }
     }
 }
 
-#[derive(Debug, Clone)]
+// The instructions in `isa::INSTRUCTION_SET`
+#[derive(Debug, Clone, PartialEq)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum Operation {
     // Data 
@@ -88,47 +488,24 @@ impl Operation {
     pub fn get_name(&self) -> String {
         match self {
             Operation::NOOP => "|         ".to_string(),
-            Operation::IMM => "| IMM     ".to_string(),
-            Operation::MOV => "| MOV     ".to_string(),
-            Operation::ADD => "| ADD     ".to_string(),
-            Operation::ADDC => "| ADDC    ".to_string(),
-            Operation::OUT => "| OUT     ".to_string(),
-            Operation::JMP => "| JMP     ".to_string(),
-            Operation::BIE => "| BIE     ".to_string(),
-            Operation::SHR => "| SHR     ".to_string(),
-            Operation::NOT => "| NOT     ".to_string(),
+            _ => format!("| {:<8}", self.definition().mnemonic),
         }
     }
 }
 
-struct CompileMessage {
-    line: usize,
-    message: String,
-}
-
 impl Operation {
-    // What operands this intruction requires (A, B)
+    // What operands this intruction requires (A, B). X arguments read the
+    // accumalator instead of A and don't write to it, so it isn't needed.
     pub fn needed_oprands(&self, args: &OperationArgs) -> (Option<Oprand>, Option<Oprand>) {
-        match self {
-            Operation::NOOP => (None, None),
-            Operation::IMM => (Some(Oprand::Register(0)), Some(Oprand::Immediate(0))),
-            Operation::MOV => (Some(Oprand::Register(0)), Some(Oprand::Register(0))),
-            Operation::ADD | Operation::ADDC => match args {
-                OperationArgs::None => (Some(Oprand::Register(0)), Some(Oprand::Register(0))), // No prefix
-                OperationArgs::S => (Some(Oprand::Register(0)), Some(Oprand::Register(0))),    // S
-                OperationArgs::U => (Some(Oprand::Register(0)), Some(Oprand::Register(0))),    // U
-                OperationArgs::X => (None, Some(Oprand::Register(0))),                         // X
-            },
-            Operation::SHR => (Some(Oprand::Register(0)), Some(Oprand::Register(0))),
-            Operation::NOT => (Some(Oprand::Register(0)), Some(Oprand::Register(0))),
-            Operation::OUT => (Some(Oprand::Port(0)), Some(Oprand::Register(0))),
-            Operation::JMP | Operation::BIE =>(Some(Oprand::Immediate(0)), None),
-            
+        let (a, b) = self.definition().oprands.clone();
+        match args {
+            OperationArgs::X => (None, b),
+            _ => (a, b),
         }
     }
 
     pub fn is_alu_operation(&self) -> bool {
-        matches!(self, Operation::ADD | Operation::ADDC)
+        self.definition().alu_arguments
     }
 }
 
@@ -146,15 +523,28 @@ pub struct Instruction {
     pub operation_args: OperationArgs,
     pub a: Oprand,
     pub b: Oprand,
+    /// The line the instruction was assembled from, if it came from source
+    pub source: Option<SourceLocation>,
 }
 
 impl Instruction {
+    pub fn new(operation: Operation, operation_args: OperationArgs, a: Oprand, b: Oprand) -> Self {
+        Self {
+            operation,
+            operation_args,
+            a,
+            b,
+            source: None,
+        }
+    }
+
     pub fn none() -> Self {
         Self {
             operation: Operation::NOOP,
             operation_args: OperationArgs::None,
             a: Oprand::Immediate(0),
             b: Oprand::Immediate(0),
+            source: None,
         }
     }
 }
@@ -187,27 +577,73 @@ impl Oprand {
     }
 }
 
-fn operation_from_str(line: &Vec<String>) -> Result<(Operation, OperationArgs), String> {
-    if line.is_empty() {
-        return Ok((Operation::NOOP, OperationArgs::None));
+impl fmt::Display for Oprand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Oprand::Register(data) => write!(f, "R{data}"),
+            Oprand::MemoryAddress(data) => write!(f, "#{data}"),
+            Oprand::Immediate(data) => write!(f, "{data}"),
+            Oprand::Port(data) => write!(f, "%{data}"),
+        }
+    }
+}
+
+impl fmt::Display for OperationArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationArgs::None => Ok(()),
+            OperationArgs::S => write!(f, "S"),
+            OperationArgs::U => write!(f, "U"),
+            OperationArgs::X => write!(f, "X"),
+        }
     }
-    let string = line.first().unwrap().clone();
+}
+
+// Writes the instruction as it would be written in a program, like "SADD R1 R2"
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            self.operation_args,
+            self.operation.definition().mnemonic
+        )?;
+        let (a, b) = self.operation.needed_oprands(&self.operation_args);
+        if a.is_some() {
+            write!(f, " {}", self.a)?;
+        }
+        if b.is_some() {
+            write!(f, " {}", self.b)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn operation_from_str(line: &[String]) -> Result<(Operation, OperationArgs), String> {
+    let Some(string) = line.first().cloned() else {
+        return Err("Expected an instruction".to_string());
+    };
+    let (prefix, name) = string.split_at(string.chars().next().map_or(0, char::len_utf8));
     if let Ok(operation) = match_operation_name(&string) {
         Ok((operation, OperationArgs::None))
-    } else if let Ok(operation) = match_operation_name(string.get(1..).unwrap()) {
-        if operation.is_alu_operation() {
-            Ok((operation, operation_args_from_str(string).unwrap()))
-        } else {
+    } else if let Ok(operation) = match_operation_name(name) {
+        if !operation.is_alu_operation() {
             Err(format!("\"{:?}\" does not take ALU arguments", operation))
+        } else if let Ok(args) = operation_args_from_str(prefix) {
+            Ok((operation, args))
+        } else {
+            Err(format!(
+                "\"{prefix}\" is not a valid ALU argument for {:?}, use S, U or X",
+                operation
+            ))
         }
     } else {
         Err(format!("\"{string}\" is not a valid instruction"))
     }
 }
 
-fn operation_args_from_str(string: String) -> Result<OperationArgs, ()> {
-    match string.get(0..1).unwrap() {
-        // first character of word
+fn operation_args_from_str(prefix: &str) -> Result<OperationArgs, ()> {
+    match prefix {
         "U" => Ok(OperationArgs::U),
         "S" => Ok(OperationArgs::S),
         "X" => Ok(OperationArgs::X),
@@ -215,124 +651,730 @@ fn operation_args_from_str(string: String) -> Result<OperationArgs, ()> {
     }
 }
 
-fn match_operation_name(str: &str) -> Result<Operation, ()> {
-    match str {
-        "IMM" => Ok(Operation::IMM),
-        "MOV" => Ok(Operation::MOV),
-        "ADD" => Ok(Operation::ADD),
-        "ADDC" => Ok(Operation::ADDC),
-        "SHR" => Ok(Operation::SHR),
-        "NOOP" | "NOP" => Ok(Operation::NOOP),
-        "OUT" => Ok(Operation::OUT),
-        "JMP" => Ok(Operation::JMP),
-        "BIE" => Ok(Operation::BIE),
-        "NOT" => Ok(Operation::NOT),
-        _ => Err(()),
+// Suggests the instruction a misspelled one was most likely meant to be
+fn suggest_operation(word: &str) -> Option<String> {
+    let mut candidates: Vec<String> = Pseudo::NAMES.map(str::to_string).to_vec();
+    for definition in &INSTRUCTION_SET {
+        candidates.push(definition.mnemonic.to_string());
+        if definition.alu_arguments {
+            for prefix in ["S", "U", "X"] {
+                candidates.push(format!("{prefix}{}", definition.mnemonic));
+            }
+        }
     }
+    closest_match(word, candidates.iter().map(String::as_str))
+        .map(|name| format!("did you mean {name}?"))
 }
 
-fn parse_oprand_binary(str: &str) -> Result<u8, String> {
-    let string = str.to_string().replace('_', "");
-    if string.get(0..1).unwrap() == "B" {
-        let binary = string.get(1..).unwrap();
-        let mut total = 0;
-        for (place, char) in binary.chars().rev().enumerate() {
-            total += char.to_string().parse::<u8>().unwrap() * 2u8.pow(place as u32);
-        }
-        Ok(total)
+// Whether a word is an instruction, so it can't be used as a macro name
+pub(crate) fn is_operation_name(word: &str) -> bool {
+    operation_from_str(&[word.to_string()]).is_ok() || Pseudo::from_name(word).is_some()
+}
+
+fn match_operation_name(str: &str) -> Result<Operation, ()> {
+    Operation::from_mnemonic(str).ok_or(())
+}
+
+fn evaluate_oprand(expression: &str, symbols: &Symbols) -> Result<i64, String> {
+    evaluate(expression, |name| symbols.constants.get(name).copied())
+}
+
+// Describes a value that doesn't fit in an oprand, showing what an expression came to
+fn describe_value(str: &str, value: i64) -> String {
+    if is_number_literal(str) {
+        format!("\"{str}\"")
     } else {
-        Ok(string.parse().unwrap())
+        format!("\"{str}\" ({value})")
+    }
+}
+
+// Evaluates a number that fits in a word, storing negative numbers in two's
+// complement
+fn parse_word(str: &str, symbols: &Symbols, machine: &Machine) -> Result<u8, String> {
+    let max = machine.max_word() as i64;
+    let min = -(max + 1) / 2;
+    match evaluate_oprand(str, symbols)? {
+        value if (0..=max).contains(&value) => Ok(value as u8),
+        value if (min..0).contains(&value) => Ok((value + max + 1) as u8),
+        value => Err(format!(
+            "{} does not fit in {} bits ({min} to {max})",
+            describe_value(str, value),
+            machine.word_bits
+        )),
     }
 }
 
-fn parse_oprand(oprand: &str) -> Result<Oprand, String> {
-    let prefix = oprand.get(0..1);
-    if let Ok(a) = oprand.parse() {
-        Ok(Oprand::Immediate(a))
-    } else if prefix.unwrap() == "R" {
-        Ok(Oprand::Register(parse_oprand_binary(oprand.get(1..).unwrap()).unwrap()))
-    } else if prefix.unwrap() == "#" {
-        Ok(Oprand::MemoryAddress(
-            parse_oprand_binary(oprand.get(1..).unwrap()).unwrap(),
+// Evaluates the ROM line a branch jumps to, which is bounded by the ROM rather
+// than the word size
+fn parse_jump_target(str: &str, symbols: &Symbols, machine: &Machine) -> Result<u8, String> {
+    check_jump_target(str, evaluate_oprand(str, symbols)?, machine)
+}
+
+// Checks that a jump target, written as `str`, is a line of ROM
+fn check_jump_target(str: &str, value: i64, machine: &Machine) -> Result<u8, String> {
+    match value {
+        value if (0..machine.rom_lines as i64).contains(&value) => Ok(value as u8),
+        value if value < 0 => Err(format!(
+            "Jump target {} is before the start of ROM",
+            describe_value(str, value)
+        )),
+        value => Err(format!(
+            "Jump target {value} is past the end of ROM ({} lines)",
+            machine.rom_lines
+        )),
+    }
+}
+
+// Evaluates the number of a register, port or memory address and checks that it exists
+fn parse_index(str: &str, count: usize, name: &str, symbols: &Symbols) -> Result<u8, String> {
+    let index = evaluate_oprand(str, symbols)?;
+    if (0..count as i64).contains(&index) {
+        Ok(index as u8)
+    } else {
+        Err(format!(
+            "There is no {name} {index}, the highest is {}",
+            count - 1
         ))
-    } else if prefix.unwrap() == "%" {
-        Ok(Oprand::Port(parse_oprand_binary(oprand.get(1..).unwrap()).unwrap()))
-    } else if let Ok(a) = parse_oprand_binary(oprand) {
-        Ok(Oprand::Immediate(a))
     }
-    else {
-        return Err(format!("\"{oprand}\" is not a valid oprand"));
+}
+
+// Reads a plain number oprand, like a word or a jump target
+type NumberParser = fn(&str, &Symbols, &Machine) -> Result<u8, String>;
+
+fn parse_oprand(
+    oprand: &str,
+    symbols: &Symbols,
+    machine: &Machine,
+    parse_number: NumberParser,
+) -> Result<Oprand, String> {
+    let register = oprand
+        .strip_prefix('R')
+        .filter(|index| is_number_literal(index) || index.starts_with('('));
+    if let Some(alias) = symbols.aliases.get(oprand) {
+        Ok(alias.clone())
+    } else if let Some(register) = register {
+        parse_index(register, machine.registers, "register", symbols).map(Oprand::Register)
+    } else if let Some(address) = oprand.strip_prefix('#') {
+        parse_index(address, machine.ram_size, "memory address", symbols).map(Oprand::MemoryAddress)
+    } else if let Some(port) = oprand.strip_prefix('%') {
+        parse_index(port, machine.output_ports, "port", symbols).map(Oprand::Port)
+    } else if is_label_name(oprand) && !symbols.constants.contains_key(oprand) {
+        Err(format!("\"{oprand}\" is not defined"))
+    } else {
+        parse_number(oprand, symbols, machine).map(Oprand::Immediate)
     }
 }
 
-fn parse_line(line: &str) -> Result<Instruction, String> {
-    let mut line = line.to_string().to_ascii_uppercase();
-    let comment = line.find(';');
-    if let Some(comment) = comment {
-        line.truncate(comment);
+fn is_register_name(name: &str) -> bool {
+    name.strip_prefix('R')
+        .is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+}
+
+pub(crate) fn is_label_name(name: &str) -> bool {
+    let starts_like_word = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    let is_register = name.strip_prefix('R').is_some_and(is_number_literal);
+    starts_like_word
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !is_register
+        && !is_number_literal(name)
+}
+
+// Pairs each character of a line with whether it is part of a 'c' character literal
+pub(crate) fn mark_quoted(line: &str) -> impl Iterator<Item = (usize, char, bool)> + '_ {
+    let mut in_quote = false;
+    let mut escaped = false;
+    line.char_indices().map(move |(column, char)| {
+        let quoted = in_quote || char == '\'';
+        if !in_quote {
+            in_quote = char == '\'';
+        } else if escaped {
+            escaped = false;
+        } else if char == '\\' {
+            escaped = true;
+        } else if char == '\'' {
+            in_quote = false;
+        }
+        (column, char, quoted)
+    })
+}
+
+pub(crate) fn find_unquoted(line: &str, target: char) -> Option<usize> {
+    mark_quoted(line)
+        .find(|(_, char, quoted)| *char == target && !quoted)
+        .map(|(column, _, _)| column)
+}
+
+pub(crate) fn strip_comment(line: &str) -> &str {
+    match find_unquoted(line, ';') {
+        Some(comment) => &line[..comment],
+        None => line,
     }
-    let mut words: Vec<String> = line.split_whitespace().map(|s| s.to_owned()).collect();
-    let (operation, operation_args) = operation_from_str(&words)?;
-    let (a, b) = {
-        (
-            if operation.needed_oprands(&operation_args).0.is_some() {
-                words.remove(0);
-                parse_oprand(words.first().unwrap())?
-            } else {
-                Oprand::Immediate(0)
-            },
-            if operation.needed_oprands(&operation_args).1.is_some() {
-                words.remove(0);
-                parse_oprand(words.first().unwrap())?
+}
+
+// Uppercases everything except character literals
+fn uppercase_code(line: &str) -> String {
+    mark_quoted(line)
+        .map(|(_, char, quoted)| {
+            if quoted {
+                char
             } else {
-                Oprand::Immediate(0)
-            },
-        )
+                char.to_ascii_uppercase()
+            }
+        })
+        .collect()
+}
+
+// Splits a line into its whitespace separated words and where they are
+pub(crate) fn split_words(line: &str) -> Vec<(Span, &str)> {
+    let mut words = vec![];
+    let mut word_start = None;
+    for (column, char, quoted) in mark_quoted(line).chain([(line.len(), ' ', false)]) {
+        match (char.is_whitespace() && !quoted, word_start) {
+            (false, None) => word_start = Some(column),
+            (true, Some(start)) => {
+                let length = column - start;
+                words.push((Span { column: start, length }, &line[start..column]));
+                word_start = None;
+            }
+            _ => (),
+        }
+    }
+    words
+}
+
+// Joins the words of expressions written with spaces, like "loop + 2", back into
+// single oprands. Words are joined while a bracket is open, after a word ending in
+// an operator, or before a word starting with one. A word like "-1" starts a new
+// oprand, but "- 1" does not.
+pub(crate) fn join_expressions<'a>(
+    line: &'a str,
+    words: Vec<(Span, &'a str)>,
+) -> Vec<(Span, &'a str)> {
+    let mut joined: Vec<(Span, &str)> = vec![];
+    for (span, word) in words {
+        if let Some((last_span, last)) = joined.last_mut() {
+            let depth = mark_quoted(last).fold(0, |depth, (_, char, quoted)| match char {
+                '(' if !quoted => depth + 1,
+                ')' if !quoted => depth - 1,
+                _ => depth,
+            });
+            let continues =
+                last.ends_with(['+', '-', '*', '/', '%', '&', '|', '^', '~', '<', '>', '(']);
+            let binary = word == "-"
+                || word == "%"
+                || word.starts_with(['+', '*', '/', '&', '|', '^', '<', '>', ')']);
+            if depth > 0 || continues || binary {
+                let end = span.column + span.length;
+                *last = &line[last_span.column..end];
+                last_span.length = end - last_span.column;
+                continue;
+            }
+        }
+        joined.push((span, word));
+    }
+    joined
+}
+
+// Splits a "name:" label definition off the start of a line. Returns the label
+// and where it is, and the column the rest of the line starts at.
+pub(crate) fn split_label(line: &str) -> Result<(Option<(String, Span)>, usize), LineError> {
+    let code = strip_comment(line);
+    let Some(colon) = find_unquoted(code, ':') else {
+        return Ok((None, 0));
+    };
+    let column = code.len() - code.trim_start().len();
+    let label = code[..colon].trim().to_ascii_uppercase();
+    let span = Span {
+        column,
+        length: label.len(),
+    };
+    if is_label_name(&label) {
+        Ok((Some((label, span)), colon + 1))
+    } else {
+        let message = format!("\"{label}\" is not a valid label name");
+        Err(LineError::new(span, message))
+    }
+}
+
+// Reads the directive starting at column `start` of a line, if there is one
+fn parse_directive(line: &str, start: usize) -> Option<Result<Directive, LineError>> {
+    let line = uppercase_code(strip_comment(line));
+    let words: Vec<(Span, &str)> = split_words(&line[start..])
+        .into_iter()
+        .map(|(span, word)| {
+            let column = start + span.column;
+            (Span { column, ..span }, word)
+        })
+        .collect();
+    let (span, name) = *words.first()?;
+    if !name.starts_with('.') {
+        return None;
+    }
+    let end_of_line = Span {
+        column: line.trim_end().len(),
+        length: 1,
+    };
+    if name != ".EQU" && name != ".ALIAS" {
+        return Some(Err(LineError {
+            span,
+            message: format!("\"{name}\" is not a valid directive"),
+            help: closest_match(name, [".EQU", ".ALIAS", ".MACRO", ".ENDM", ".INCLUDE"])
+                .map(|name| format!("did you mean {name}?")),
+        }));
+    }
+    let Some((symbol_span, symbol)) = words.get(1) else {
+        let message = format!("{name} is missing the name to define");
+        return Some(Err(LineError::new(end_of_line, message)));
+    };
+    let Some((value_span, _)) = words.get(2) else {
+        let message = format!("{name} is missing a value for \"{symbol}\"");
+        return Some(Err(LineError::new(end_of_line, message)));
+    };
+    let value = line[value_span.column..].trim_end();
+    Some(Ok(Directive {
+        name: name.to_string(),
+        symbol: symbol.to_string(),
+        symbol_span: *symbol_span,
+        value: value.to_string(),
+        value_span: Span {
+            column: value_span.column,
+            length: value.len(),
+        },
+    }))
+}
+
+// Parses the instruction starting at column `start` of a line, also returning
+// where its operation and oprands are
+fn parse_line(
+    line: &str,
+    start: usize,
+    symbols: &Symbols,
+    machine: &Machine,
+) -> Result<(Vec<Instruction>, WrittenInstruction), LineError> {
+    let line = uppercase_code(strip_comment(line));
+    let words = split_words(&line[start..])
+        .into_iter()
+        .map(|(span, word)| {
+            let column = start + span.column;
+            (Span { column, ..span }, word)
+        })
+        .collect();
+    let (spans, words): (Vec<Span>, Vec<String>) = join_expressions(&line, words)
+        .into_iter()
+        .map(|(span, word)| (span, word.to_owned()))
+        .unzip();
+    let span = |word: usize| {
+        *spans.get(word).unwrap_or(&Span {
+            column: line.len(),
+            length: 1,
+        })
+    };
+    let pseudo = Pseudo::from_name(&words[0]);
+    let (operation, operation_args) = match pseudo {
+        // Pseudo-instructions are given their real instructions once their
+        // oprands are known
+        Some(_) => (Operation::NOOP, OperationArgs::None),
+        None => operation_from_str(&words).map_err(|message| LineError {
+            span: span(0),
+            message,
+            help: suggest_operation(&words[0]),
+        })?,
     };
-    Ok(Instruction {
-        operation,
-        operation_args,
+    let name = match pseudo {
+        Some(pseudo) => format!("{pseudo:?}"),
+        None => format!("{operation:?}"),
+    };
+    // An oprand that took in the words after it, like "R1 - 1"
+    let joined = words
+        .iter()
+        .enumerate()
+        .skip(1)
+        .find(|(_, word)| word.contains(char::is_whitespace));
+    let mut words = words.iter().enumerate().skip(1);
+    let mut next_oprand = |oprand_name: &str, parse_number: NumberParser| {
+        let Some((word, oprand)) = words.next() else {
+            let message = format!("{name} is missing oprand {oprand_name}");
+            let Some((joined_word, joined)) = joined else {
+                return Err(LineError::new(span(spans.len()), message));
+            };
+            return Err(LineError {
+                span: span(joined_word),
+                message: format!("{message}, \"{joined}\" was read as one oprand"),
+                help: joined
+                    .contains("- ")
+                    .then(|| "write negative numbers without a space after the \"-\"".to_string()),
+            });
+        };
+        match parse_oprand(oprand, symbols, machine, parse_number) {
+            Ok(oprand) => Ok((oprand, span(word))),
+            Err(message) => Err(LineError {
+                span: span(word),
+                message,
+                help: is_label_name(oprand)
+                    .then(|| symbols.suggest(oprand))
+                    .flatten(),
+            }),
+        }
+    };
+    let needed_oprands = match pseudo {
+        Some(pseudo) => pseudo.needed_oprands(),
+        None => operation.needed_oprands(&operation_args),
+    };
+    let branches = operation.definition().branch != Branch::Never;
+    // Jump targets are ROM lines, which can be more than a word holds
+    let parse_a: NumberParser = if branches {
+        parse_jump_target
+    } else {
+        parse_word
+    };
+    let (a, a_span) = if needed_oprands.0.is_some() {
+        next_oprand("A", parse_a)?
+    } else {
+        (Oprand::Immediate(0), span(0))
+    };
+    // Aliases aren't parsed as jump targets, so they are checked here
+    if branches {
+        check_jump_target(&a.to_string(), a.data() as i64, machine)
+            .map_err(|message| LineError::new(a_span, message))?;
+    }
+    let (b, b_span) = if needed_oprands.1.is_some() {
+        next_oprand("B", parse_word)?
+    } else {
+        (Oprand::Immediate(0), span(0))
+    };
+    if let Some((word, oprand)) = words.next() {
+        let message = match needed_oprands.0.iter().chain(&needed_oprands.1).count() {
+            0 => format!("{name} does not take any oprands"),
+            1 => format!("{name} only takes 1 oprand"),
+            count => format!("{name} only takes {count} oprands"),
+        };
+        return Err(LineError::new(
+            span(word),
+            format!("Unexpected oprand \"{oprand}\", {message}"),
+        ));
+    }
+    let instructions = match pseudo {
+        Some(pseudo) => pseudo.expand(a.clone(), b.clone()),
+        None => vec![Instruction::new(
+            operation,
+            operation_args,
+            a.clone(),
+            b.clone(),
+        )],
+    };
+    let written = WrittenInstruction {
+        name,
+        needed_oprands,
         a,
         b,
-    })
+        operation: span(0),
+        oprands: (a_span, b_span),
+    };
+    Ok((instructions, written))
 }
 
-fn add_warnings(instructions: &Vec<Instruction>, warnings: &mut Vec<CompileMessage>) {
-    for (line_num, instruction) in instructions.iter().enumerate() {
-        let needed_oprands = instruction
-            .operation
-            .needed_oprands(&instruction.operation_args);
-        if let Some(a) = needed_oprands.0 {
-            if std::mem::discriminant(&a) != std::mem::discriminant(&instruction.a) {
-                warnings.push(CompileMessage {
-                    line: line_num,
-                    message: format!(
-                        "{:?} takes a {} for oprand A, not a {}",
-                        instruction.operation,
-                        a.get_oprand_name(),
-                        &instruction.a.get_oprand_name()
-                    ),
-                })
+fn add_warnings(
+    instructions: &[Instruction],
+    sources: &[InstructionSource],
+    instruction_sources: &[Option<&InstructionSource>],
+    aliases: &[AliasSource],
+    machine: &Machine,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    let mut alias_lines = HashMap::new();
+    for alias in aliases {
+        let name = &alias.name;
+        if is_register_name(name) {
+            let message = format!("Alias \"{name}\" hides register {name}");
+            warnings.push(alias.line.warning(alias.span, message));
+        }
+        if let Some(defined_on) = alias_lines.insert(name, alias.line) {
+            let message = format!(
+                "Alias \"{name}\" is redefined, it was defined on {}",
+                defined_on.describe()
+            );
+            warnings.push(alias.line.warning(alias.span, message));
+        }
+    }
+    for source in sources {
+        let written = &source.written;
+        if let Some(a) = &written.needed_oprands.0 {
+            if std::mem::discriminant(a) != std::mem::discriminant(&written.a) {
+                let message = format!(
+                    "{} takes a {} for oprand A, not a {}",
+                    written.name,
+                    a.get_oprand_name(),
+                    &written.a.get_oprand_name()
+                );
+                warnings.push(source.line.warning(written.oprands.0, message))
             }
         }
-        if let Some(b) = needed_oprands.1 {
-            if std::mem::discriminant(&b) != std::mem::discriminant(&instruction.b) {
-                warnings.push(CompileMessage {
-                    line: line_num,
-                    message: format!(
-                        "{:?} takes a {} for oprand B, not a {}",
-                        instruction.operation,
-                        b.get_oprand_name(),
-                        &instruction.b.get_oprand_name()
-                    ),
-                })
+        if let Some(b) = &written.needed_oprands.1 {
+            if std::mem::discriminant(b) != std::mem::discriminant(&written.b) {
+                let message = format!(
+                    "{} takes a {} for oprand B, not a {}",
+                    written.name,
+                    b.get_oprand_name(),
+                    &written.b.get_oprand_name()
+                );
+                warnings.push(source.line.warning(written.oprands.1, message))
             }
         }
     }
-    if instructions.len() > 32 {
-        warnings.push(CompileMessage {
-            line: 32,
-            message: format!("Too many lines of instruction ({}/32)", instructions.len()),
-        })
+    add_hazard_warnings(instructions, instruction_sources, warnings);
+    add_delay_slot_warnings(instructions, instruction_sources, warnings);
+    add_control_flow_warnings(instructions, instruction_sources, warnings);
+    add_dataflow_warnings(instructions, instruction_sources, warnings);
+    // Pseudo-instructions count every instruction they expand to
+    if let Some(source) = sources
+        .iter()
+        .find(|source| source.addresses.end > machine.rom_lines)
+    {
+        let message = format!(
+            "Too many lines of instruction ({}/{})",
+            instructions.len(),
+            machine.rom_lines
+        );
+        warnings.push(source.line.warning(source.written.operation, message))
+    }
+}
+
+// Describes the line the instruction at `address` was assembled from
+fn describe(lines: &[Option<&InstructionSource>], address: usize) -> String {
+    match lines[address] {
+        Some(source) => source.line.describe(),
+        None => "a line added by the assembler".to_string(),
+    }
+}
+
+// Warns about each hazard, given the line each instruction was assembled from
+fn add_hazard_warnings(
+    instructions: &[Instruction],
+    lines: &[Option<&InstructionSource>],
+    warnings: &mut Vec<Diagnostic>,
+) {
+    for hazard in find_hazards(instructions, &Pipeline::EMULATOR) {
+        let (reader, message, help, note) = match hazard {
+            Hazard::Register {
+                reader,
+                writer,
+                register,
+                noops,
+            } => {
+                let message = format!(
+                    "\"{}\" reads R{register} before \"{}\" on {} has written it",
+                    instructions[reader],
+                    instructions[writer],
+                    describe(lines, writer)
+                );
+                let plural = if noops == 1 { "" } else { "s" };
+                let help = format!("put {noops} NOOP{plural} between them");
+                (reader, message, help, None)
+            }
+            Hazard::Alu {
+                reader,
+                overwriter,
+                intended,
+                value,
+            } => {
+                let (pronoun, verb) = match value {
+                    AluValue::Accumalator => ("it", "was"),
+                    AluValue::Flags => ("them", "were"),
+                };
+                let message = format!(
+                    "\"{}\" uses the {}, but \"{}\" on {} runs just before it and overwrites {pronoun}",
+                    instructions[reader],
+                    value.name(),
+                    instructions[overwriter],
+                    describe(lines, overwriter),
+                );
+                let help = "every instruction overwrites the accumalator and flags, even NOOP, \
+                            so the ALU instruction has to run right before it";
+                let note = intended.and_then(|intended| lines[intended]).map(|source| {
+                    let message = format!("the {} {verb} last set here", value.name());
+                    source
+                        .line
+                        .diagnostic(Severity::Note, source.written.operation, message)
+                });
+                (reader, message, help.to_string(), note)
+            }
+        };
+        // Added NOOPs don't read anything, so every reader has a line
+        let Some(source) = lines[reader] else {
+            continue;
+        };
+        let mut warning = source
+            .line
+            .warning(source.written.operation, message)
+            .with_help(Some(help));
+        if let Some(note) = note {
+            warning = warning.with_note(note);
+        }
+        warnings.push(warning);
+    }
+}
+
+// Warns about instructions with side effects that run after a branch before
+// it takes effect
+fn add_delay_slot_warnings(
+    instructions: &[Instruction],
+    lines: &[Option<&InstructionSource>],
+    warnings: &mut Vec<Diagnostic>,
+) {
+    for (branch, instruction) in instructions.iter().enumerate() {
+        if !is_branch(instruction) {
+            continue;
+        }
+        for slot in shadow(instructions, branch, &Pipeline::EMULATOR) {
+            let Some(source) = lines[slot] else {
+                continue;
+            };
+            // Instructions written before the branch, like ones
+            // `fill_delay_slots` moved there, were meant to run first anyway
+            let written_before =
+                lines[branch].is_some_and(|branch| source.addresses.start < branch.addresses.start);
+            if !has_side_effects(&instructions[slot]) || written_before {
+                continue;
+            }
+            let runs = match instruction.operation.definition().branch {
+                Branch::Always => "before the program jumps",
+                _ => "whether or not the branch is taken",
+            };
+            let message = format!(
+                "\"{}\" is in the delay slot of \"{instruction}\" on {}, so it runs {runs}",
+                instructions[slot],
+                describe(lines, branch)
+            );
+            let help = "put a NOOP after the branch, or assemble with --fill-delay-slots";
+            warnings.push(
+                source
+                    .line
+                    .warning(source.written.operation, message)
+                    .with_help(Some(help.to_string())),
+            );
+        }
+    }
+}
+
+// Warns about lines that can never run, and loops that never OUT that the
+// program can't leave
+fn add_control_flow_warnings(
+    instructions: &[Instruction],
+    lines: &[Option<&InstructionSource>],
+    warnings: &mut Vec<Diagnostic>,
+) {
+    let graph = ControlFlowGraph::new(instructions, &Pipeline::EMULATOR);
+    let reachable = graph.reachable();
+    for (block, basic_block) in graph.blocks.iter().enumerate() {
+        // Only the first of a run of blocks that can't be run is warned about
+        if reachable[block] || block > 0 && !reachable[block - 1] {
+            continue;
+        }
+        let Some((address, source)) = basic_block
+            .addresses
+            .clone()
+            .find_map(|address| Some((address, lines[address]?)))
+        else {
+            continue;
+        };
+        let message = format!("\"{}\" can never be run", instructions[address]);
+        let help = "nothing jumps to it, and the program always jumps away before getting to it";
+        warnings.push(
+            source
+                .line
+                .warning(source.written.operation, message)
+                .with_help(Some(help.to_string())),
+        );
+    }
+    for blocks in graph.endless_loops() {
+        let addresses: Vec<usize> = blocks
+            .iter()
+            .flat_map(|block| graph.blocks[*block].addresses.clone())
+            .collect();
+        let outputs = addresses
+            .iter()
+            .any(|address| instructions[*address].write_back() == WriteBack::Out);
+        // Loops that only jump are the program stopping on purpose
+        let does_something = addresses
+            .iter()
+            .any(|address| written_register(&instructions[*address]).is_some());
+        if outputs || !does_something {
+            continue;
+        }
+        let last = *addresses.last().unwrap();
+        let branch = addresses
+            .iter()
+            .rev()
+            .find(|address| is_branch(&instructions[**address]))
+            .unwrap_or(&last);
+        let Some(source) = lines[*branch] else {
+            continue;
+        };
+        let message = format!(
+            "\"{}\" loops forever without an OUT, so the ports never change again",
+            instructions[*branch]
+        );
+        let help =
+            "add an OUT to the loop, or end the program with a jump to itself once it is done";
+        let mut warning = source
+            .line
+            .warning(source.written.operation, message)
+            .with_help(Some(help.to_string()));
+        if let Some(start) = lines[addresses[0]] {
+            let note = start.line.diagnostic(
+                Severity::Note,
+                start.written.operation,
+                "the loop starts here".to_string(),
+            );
+            warning = warning.with_note(note);
+        }
+        warnings.push(warning);
+    }
+}
+
+// Warns about registers read before they're written, and values written to
+// registers that nothing reads
+fn add_dataflow_warnings(
+    instructions: &[Instruction],
+    lines: &[Option<&InstructionSource>],
+    warnings: &mut Vec<Diagnostic>,
+) {
+    for issue in find_register_issues(instructions, &Pipeline::EMULATOR) {
+        let (address, register, message, help) = match issue {
+            RegisterIssue::Uninitialized { reader, register } => (
+                reader,
+                register,
+                format!(
+                    "\"{}\" may read R{register} before anything has written it",
+                    instructions[reader]
+                ),
+                format!(
+                    "registers start at 0 in the emulator, but not always in minecraft, \
+                     so write R{register} first with \"IMM R{register} 0\""
+                ),
+            ),
+            RegisterIssue::DeadWrite { writer, register } => (
+                writer,
+                register,
+                format!(
+                    "\"{}\" writes R{register}, but nothing reads that value",
+                    instructions[writer]
+                ),
+                "it is always written again, or never read, after this line".to_string(),
+            ),
+        };
+        let Some(source) = lines[address] else {
+            continue;
+        };
+        // Point at the oprand the register was written as, if it was
+        let written = &source.written;
+        let span = if matches!(written.a, Oprand::Register(a) if a == register) {
+            written.oprands.0
+        } else if matches!(written.b, Oprand::Register(b) if b == register) {
+            written.oprands.1
+        } else {
+            written.operation
+        };
+        warnings.push(source.line.warning(span, message).with_help(Some(help)));
     }
 }
@@ -0,0 +1,479 @@
+use crate::diagnostic::*;
+use crate::parser::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Pastes in included files and expands macros before the lines of a program
+// are parsed:
+//
+// .macro show port value
+//     IMM R1 value
+//     OUT port R1
+// .endm
+//
+//     show %0 B1010
+//
+// Parameters are replaced wherever their name appears as a whole word, and
+// labels defined in the body are renamed for each expansion so a macro can be
+// used more than once.
+//
+// .include "display.elt"
+//
+// Included files are found relative to the file including them.
+
+// Far more than fits in ROM, but stops macros that use each other many times
+// from taking forever to expand
+const MAX_EXPANDED_LINES: usize = 4096;
+
+/// A line of the program after preprocessing, and where it came from.
+#[derive(Debug, Clone)]
+pub struct SourceLine {
+    pub text: String,
+    /// The included file the line is in, or `None` for the file being assembled
+    pub file: Option<String>,
+    /// The line in the source file, or in the macro body it was expanded from
+    pub line: usize,
+    /// The macro calls this line was expanded from, innermost first
+    pub expansions: Vec<Expansion>,
+    /// The .include lines that brought in the line's file, innermost first
+    pub includes: Vec<Location>,
+    // The columns in the source each byte of `text` came from, with one more
+    // entry for the end of the line. Empty if the text is unchanged.
+    columns: Vec<(usize, usize)>,
+    // The line as it is written in its file, before macro arguments were
+    // substituted
+    original: String,
+}
+
+/// A place in the source, like the call of a macro.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub file: Option<String>,
+    pub line: usize,
+    pub span: Span,
+    /// The line as it is written in its file
+    pub text: String,
+}
+
+impl Location {
+    fn note(&self, message: String) -> Diagnostic {
+        Diagnostic::note(self.line, self.span.column, message)
+            .with_length(self.span.length)
+            .with_file(self.file.clone())
+            .with_line_text(self.text.clone())
+    }
+}
+
+/// A call of a macro.
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub name: String,
+    pub call: Location,
+}
+
+impl SourceLine {
+    // The place a span of the line was written
+    fn location(&self, span: Span) -> Location {
+        Location {
+            file: self.file.clone(),
+            line: self.line,
+            span: self.original_span(span),
+            text: self.original.clone(),
+        }
+    }
+
+    /// Describes where the line is for messages, like "line 3 of display.elt".
+    pub fn describe(&self) -> String {
+        match &self.file {
+            Some(file) => format!("line {} of {file}", self.line + 1),
+            None => format!("line {}", self.line + 1),
+        }
+    }
+
+    // Finds where a span of the expanded text was written in the source
+    fn original_span(&self, span: Span) -> Span {
+        let Some(end_of_line) = self.columns.last() else {
+            return span;
+        };
+        let column = |column: usize| *self.columns.get(column).unwrap_or(end_of_line);
+        let start = column(span.column).0;
+        let end = column(span.column + span.length.max(1) - 1).1;
+        Span {
+            column: start,
+            length: end.saturating_sub(start).max(1),
+        }
+    }
+
+    pub fn diagnostic(&self, severity: Severity, span: Span, message: String) -> Diagnostic {
+        let location = self.location(span);
+        let mut diagnostic = Diagnostic {
+            severity,
+            ..location.note(message)
+        };
+        for expansion in &self.expansions {
+            let message = format!("in this expansion of macro {}", expansion.name);
+            diagnostic = diagnostic.with_note(expansion.call.note(message));
+        }
+        for include in &self.includes {
+            let message = "in the file included from here".to_string();
+            diagnostic = diagnostic.with_note(include.note(message));
+        }
+        diagnostic
+    }
+
+    pub fn error(&self, span: Span, message: String) -> Diagnostic {
+        self.diagnostic(Severity::Error, span, message)
+    }
+
+    pub fn warning(&self, span: Span, message: String) -> Diagnostic {
+        self.diagnostic(Severity::Warning, span, message)
+    }
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<SourceLine>,
+    // Labels defined in the body, which are renamed for every expansion
+    labels: Vec<String>,
+}
+
+#[derive(Default)]
+struct Preprocessor {
+    macros: HashMap<String, Macro>,
+    expansion_count: usize,
+    // The files being read, to stop files from including themselves
+    reading: Vec<PathBuf>,
+    lines: Vec<SourceLine>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Pastes in the files included by `source` and expands its macros, returning
+/// the lines left to assemble. Files are included relative to `path`, the file
+/// `source` was read from, if there is one.
+pub fn preprocess(
+    source: &str,
+    path: Option<&Path>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<SourceLine> {
+    let mut preprocessor = Preprocessor::default();
+    if let Some(path) = path.and_then(|path| path.canonicalize().ok()) {
+        preprocessor.reading.push(path);
+    }
+    preprocessor.read(source, path, None, &[]);
+    diagnostics.append(&mut preprocessor.diagnostics);
+    preprocessor.lines
+}
+
+impl Preprocessor {
+    fn error(&mut self, line: &SourceLine, span: Span, message: String) {
+        self.diagnostics.push(line.error(span, message));
+    }
+
+    // Reads the lines of a file, which was included by `includes` if it isn't
+    // the one being assembled
+    fn read(
+        &mut self,
+        source: &str,
+        path: Option<&Path>,
+        file: Option<String>,
+        includes: &[Location],
+    ) {
+        // The macro being defined, with its name and the line defining it
+        let mut definition: Option<(String, SourceLine, Macro)> = None;
+        for (line_num, text) in source.lines().enumerate() {
+            let line = SourceLine {
+                text: text.to_string(),
+                file: file.clone(),
+                line: line_num,
+                expansions: vec![],
+                includes: includes.to_vec(),
+                columns: vec![],
+                original: text.to_string(),
+            };
+            let words = split_words(strip_comment(&line.text));
+            let first_word = words
+                .first()
+                .map(|(span, word)| (*span, word.to_ascii_uppercase()));
+            match (first_word, &mut definition) {
+                (Some((span, word)), None) if word == ".INCLUDE" => {
+                    self.include(&line, span, path);
+                }
+                (Some((span, word)), Some(_)) if word == ".INCLUDE" => {
+                    let message = "Files can't be included inside macros".to_string();
+                    self.error(&line, span, message);
+                }
+                (Some((_, word)), None) if word == ".MACRO" => {
+                    match self.start_macro(&line, &words[1..]) {
+                        Ok((name, parameters)) => {
+                            let new_macro = Macro {
+                                parameters,
+                                body: vec![],
+                                labels: vec![],
+                            };
+                            definition = Some((name, line, new_macro));
+                        }
+                        Err((span, message)) => self.error(&line, span, message),
+                    }
+                }
+                (Some((span, word)), Some(_)) if word == ".MACRO" => {
+                    let message = "Macros can't be defined inside other macros".to_string();
+                    self.error(&line, span, message);
+                }
+                (Some((span, word)), _) if word == ".ENDM" => match definition.take() {
+                    Some((name, _, new_macro)) => {
+                        self.macros.insert(name, new_macro);
+                    }
+                    None => {
+                        let message = ".ENDM without a .MACRO to end".to_string();
+                        self.error(&line, span, message);
+                    }
+                },
+                (_, Some((_, _, new_macro))) => {
+                    if let Ok((Some((label, _)), _)) = split_label(&line.text) {
+                        new_macro.labels.push(label);
+                    }
+                    new_macro.body.push(line);
+                }
+                (_, None) => self.expand(line),
+            }
+        }
+        if let Some((name, line, _)) = definition {
+            let span = Span {
+                column: 0,
+                length: line.text.trim_end().len(),
+            };
+            let message = format!("Macro {name} is missing its .ENDM");
+            self.error(&line, span, message);
+        }
+    }
+
+    // Reads the file named by an `.include "file"` line
+    fn include(&mut self, line: &SourceLine, directive: Span, path: Option<&Path>) {
+        let code = strip_comment(&line.text);
+        let start = directive.column + directive.length;
+        let argument = code[start..].trim();
+        let span = Span {
+            column: code.len() - code[start..].trim_start().len(),
+            length: argument.len(),
+        };
+        let Some(name) = argument
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+        else {
+            let message = ".INCLUDE needs a file name in quotes, like .include \"display.elt\"";
+            let span = if argument.is_empty() {
+                Span {
+                    column: code.trim_end().len(),
+                    length: 1,
+                }
+            } else {
+                span
+            };
+            self.error(line, span, message.to_string());
+            return;
+        };
+        let directory = path.and_then(Path::parent).unwrap_or(Path::new(""));
+        let included = directory.join(name);
+        let file_name = included.to_string_lossy().to_string();
+        let source = match included
+            .canonicalize()
+            .and_then(|path| Ok((std::fs::read_to_string(&path)?, path)))
+        {
+            Ok((source, path)) if !self.reading.contains(&path) => {
+                self.reading.push(path);
+                source
+            }
+            Ok(_) => {
+                let message =
+                    format!("Including {file_name} would never end, it is already being included");
+                self.error(line, span, message);
+                return;
+            }
+            Err(error) => {
+                self.error(line, span, format!("Could not open {file_name}: {error}"));
+                return;
+            }
+        };
+        let mut includes = vec![line.location(span)];
+        includes.extend(line.includes.iter().cloned());
+        self.read(&source, Some(&included), Some(file_name), &includes);
+        self.reading.pop();
+    }
+
+    // Reads the name and parameters of a ".macro name parameters..." line
+    fn start_macro(
+        &self,
+        line: &SourceLine,
+        words: &[(Span, &str)],
+    ) -> Result<(String, Vec<String>), (Span, String)> {
+        let Some((name_span, name)) = words.first() else {
+            let span = Span {
+                column: line.text.trim_end().len(),
+                length: 1,
+            };
+            return Err((span, ".MACRO is missing a name".to_string()));
+        };
+        let name = name.to_ascii_uppercase();
+        if !is_label_name(&name) {
+            return Err((*name_span, format!("\"{name}\" is not a valid macro name")));
+        }
+        if is_operation_name(&name) {
+            let message = format!("Macro {name} has the same name as an instruction");
+            return Err((*name_span, message));
+        }
+        if self.macros.contains_key(&name) {
+            return Err((*name_span, format!("Macro {name} is already defined")));
+        }
+        let mut parameters: Vec<String> = vec![];
+        for (span, parameter) in &words[1..] {
+            let parameter = parameter.to_ascii_uppercase();
+            if !is_label_name(&parameter) {
+                let message = format!("\"{parameter}\" is not a valid parameter name");
+                return Err((*span, message));
+            }
+            if parameters.contains(&parameter) {
+                let message = format!("Macro {name} already has a parameter {parameter}");
+                return Err((*span, message));
+            }
+            parameters.push(parameter);
+        }
+        Ok((name, parameters))
+    }
+
+    // Adds a line to the program, expanding it if it calls a macro
+    fn expand(&mut self, line: SourceLine) {
+        let Ok((label, rest)) = split_label(&line.text) else {
+            self.lines.push(line);
+            return;
+        };
+        let code = strip_comment(&line.text);
+        let words: Vec<(Span, &str)> = split_words(&code[rest..])
+            .into_iter()
+            .map(|(span, word)| {
+                let column = rest + span.column;
+                (Span { column, ..span }, word)
+            })
+            .collect();
+        let Some((name_span, name)) = words.first() else {
+            self.lines.push(line);
+            return;
+        };
+        let name = name.to_ascii_uppercase();
+        let Some(called) = self.macros.get(&name) else {
+            self.lines.push(line);
+            return;
+        };
+        if line
+            .expansions
+            .iter()
+            .any(|expansion| expansion.name == name)
+        {
+            let message = format!("Macro {name} uses itself, so it would never end");
+            self.error(&line, *name_span, message);
+            return;
+        }
+        if self.lines.len() + called.body.len() > MAX_EXPANDED_LINES {
+            let message = format!("Macros expand to more than {MAX_EXPANDED_LINES} lines");
+            self.error(&line, *name_span, message);
+            return;
+        }
+        let arguments = join_expressions(code, words[1..].to_vec());
+        if arguments.len() != called.parameters.len() {
+            let plural = |count: usize| if count == 1 { "" } else { "s" };
+            let (expected, given) = (called.parameters.len(), arguments.len());
+            let message = format!(
+                "Macro {name} takes {expected} argument{} but was given {given}",
+                plural(expected)
+            );
+            self.error(&line, *name_span, message);
+            return;
+        }
+        self.expansion_count += 1;
+        let mut replacements: HashMap<String, String> = called
+            .parameters
+            .iter()
+            .cloned()
+            .zip(arguments.iter().map(|(_, argument)| argument.to_string()))
+            .collect();
+        for label in &called.labels {
+            replacements.insert(label.clone(), format!("{label}__{}", self.expansion_count));
+        }
+        let mut expansions = vec![Expansion {
+            name,
+            call: line.location(*name_span),
+        }];
+        expansions.extend(line.expansions.iter().cloned());
+        // A label before the call is kept on its own line
+        if label.is_some() {
+            let mut label_line = line.clone();
+            label_line.text.truncate(rest);
+            self.lines.push(label_line);
+        }
+        let body: Vec<SourceLine> = called
+            .body
+            .iter()
+            .map(|body_line| {
+                let (text, columns) = substitute(&body_line.text, &replacements);
+                SourceLine {
+                    text,
+                    expansions: expansions.clone(),
+                    columns: body_line.map_columns(columns),
+                    ..body_line.clone()
+                }
+            })
+            .collect();
+        for body_line in body {
+            self.expand(body_line);
+        }
+    }
+}
+
+impl SourceLine {
+    // Maps columns of text made from this line back to where this line came from
+    fn map_columns(&self, columns: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+        if self.columns.is_empty() {
+            return columns;
+        }
+        columns
+            .into_iter()
+            .map(|(start, end)| {
+                let span = self.original_span(Span {
+                    column: start,
+                    length: end - start,
+                });
+                (span.column, span.column + span.length)
+            })
+            .collect()
+    }
+}
+
+// Replaces whole words of a line before its comment, returning the new line and
+// the columns each of its bytes came from
+fn substitute(text: &str, replacements: &HashMap<String, String>) -> (String, Vec<(usize, usize)>) {
+    let code_end = strip_comment(text).len();
+    let is_word_char = |char: char| char.is_ascii_alphanumeric() || char == '_' || char == '$';
+    let mut output = String::new();
+    let mut columns = vec![];
+    let mut characters = mark_quoted(text).peekable();
+    while let Some((column, char, quoted)) = characters.next() {
+        if quoted || column >= code_end || !is_word_char(char) {
+            output.push(char);
+            columns.extend((0..char.len_utf8()).map(|_| (column, column + char.len_utf8())));
+            continue;
+        }
+        let mut end = column + 1;
+        while let Some((next, _, _)) =
+            characters.next_if(|(_, char, quoted)| !quoted && is_word_char(*char))
+        {
+            end = next + 1;
+        }
+        let word = &text[column..end];
+        let replacement = replacements
+            .get(&word.to_ascii_uppercase())
+            .map_or(word, String::as_str);
+        output += replacement;
+        columns.extend((0..replacement.len()).map(|_| (column, end)));
+    }
+    columns.push((text.len(), text.len() + 1));
+    (output, columns)
+}
@@ -0,0 +1,82 @@
+use crate::parser::*;
+
+// Instructions the computer doesn't have, which the assembler expands into
+// ones it does. R0 always reads as 0, so it is written to when only the
+// accumalator is wanted.
+//
+// SUB Ra Rb    NOT R0 Rb, UADDC Ra Ra      Ra = Ra + ~Rb + 1
+// INC Ra       SADDC Ra R0                 Ra = Ra + 0 + 1
+// DEC Ra       NOT R0 R0, UADD Ra Ra       Ra = Ra + 255
+// CLR Ra       IMM Ra 0
+// CMP Ra Rb    ADD Ra Rb                   Only sets the flags
+// NEG Ra       NOT R0 Ra, UADDC Ra R0      Ra = ~Ra + 0 + 1
+// LDI Ra n     IMM Ra n
+
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+pub(crate) enum Pseudo {
+    SUB,
+    INC,
+    DEC,
+    CLR,
+    CMP,
+    NEG,
+    LDI,
+}
+
+impl Pseudo {
+    pub(crate) const NAMES: [&'static str; 7] = ["SUB", "INC", "DEC", "CLR", "CMP", "NEG", "LDI"];
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "SUB" => Some(Pseudo::SUB),
+            "INC" => Some(Pseudo::INC),
+            "DEC" => Some(Pseudo::DEC),
+            "CLR" => Some(Pseudo::CLR),
+            "CMP" => Some(Pseudo::CMP),
+            "NEG" => Some(Pseudo::NEG),
+            "LDI" => Some(Pseudo::LDI),
+            _ => None,
+        }
+    }
+
+    // What oprands it takes (A, B), like `Operation::needed_oprands`
+    pub(crate) fn needed_oprands(&self) -> (Option<Oprand>, Option<Oprand>) {
+        match self {
+            Pseudo::SUB | Pseudo::CMP => (Some(Oprand::Register(0)), Some(Oprand::Register(0))),
+            Pseudo::INC | Pseudo::DEC | Pseudo::CLR | Pseudo::NEG => {
+                (Some(Oprand::Register(0)), None)
+            }
+            Pseudo::LDI => (Some(Oprand::Register(0)), Some(Oprand::Immediate(0))),
+        }
+    }
+
+    // How many instructions it expands to
+    pub(crate) fn size(&self) -> usize {
+        self.expand(Oprand::Register(0), Oprand::Register(0)).len()
+    }
+
+    pub(crate) fn expand(&self, a: Oprand, b: Oprand) -> Vec<Instruction> {
+        use Operation::*;
+        use OperationArgs::{None, S, U};
+        let zero = Oprand::Register(0);
+        match self {
+            Pseudo::SUB => vec![
+                Instruction::new(NOT, None, zero, b),
+                Instruction::new(ADDC, U, a.clone(), a),
+            ],
+            Pseudo::INC => vec![Instruction::new(ADDC, S, a, zero)],
+            Pseudo::DEC => vec![
+                Instruction::new(NOT, None, zero.clone(), zero),
+                Instruction::new(ADD, U, a.clone(), a),
+            ],
+            Pseudo::CLR => vec![Instruction::new(IMM, None, a, Oprand::Immediate(0))],
+            Pseudo::CMP => vec![Instruction::new(ADD, None, a, b)],
+            Pseudo::NEG => vec![
+                Instruction::new(NOT, None, zero.clone(), a.clone()),
+                Instruction::new(ADDC, U, a, zero),
+            ],
+            Pseudo::LDI => vec![Instruction::new(IMM, None, a, b)],
+        }
+    }
+}
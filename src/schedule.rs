@@ -0,0 +1,314 @@
+use crate::hazards::*;
+use crate::isa::*;
+use crate::machine::Machine;
+use crate::parser::*;
+
+// Rearranges a program so it runs as if each instruction finished before the
+// next one started, fixing the hazards `find_hazards` finds:
+//
+// - An instruction reading a register too soon after it was written gets
+//   NOOPs put before it.
+// - An instruction using the accumalator or flags after another instruction
+//   overwrote them gets that instruction moved before the ALU instruction, if
+//   it doesn't depend on anything in between.
+//
+// Branches followed by instructions with side effects can also have their
+// delay slots filled, so those instructions only run once the branch is done.
+// A JMP gets the instruction before it where that doesn't change anything,
+// otherwise the slots get NOOPs.
+//
+// Jump targets are moved along with the instructions they point at. Hazards
+// that can't be fixed this way are left for the warnings to point out.
+
+// The most changes to try, so a fix that makes another hazard can't go on forever
+const MAX_FIXES: usize = 256;
+
+/// A program after scheduling.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pub instructions: Vec<Instruction>,
+    /// The address each instruction had before, or `None` for added NOOPs
+    pub from: Vec<Option<usize>>,
+    // The new address of every old address in the program
+    relocated: Vec<usize>,
+}
+
+impl Schedule {
+    /// Where an instruction that was at `address` is now. Addresses after the
+    /// end of the program move by however many lines were added.
+    pub fn relocate(&self, address: usize) -> usize {
+        match self.relocated.get(address) {
+            Some(relocated) => *relocated,
+            None => address + self.instructions.len() - self.relocated.len(),
+        }
+    }
+}
+
+/// Why a program couldn't be scheduled. `address` is the instruction, before
+/// scheduling, whose NOOPs or moved target no longer fit in ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleError {
+    pub address: usize,
+    pub message: String,
+}
+
+// An instruction being scheduled, with jump targets still as old addresses
+struct Item {
+    instruction: Instruction,
+    from: Option<usize>,
+    // The old addresses that now mean this instruction
+    labels: Vec<usize>,
+    // The old address of the instruction this one is here for, which is the
+    // reader for NOOPs put before it and the branch for delay slot fillers
+    cause: usize,
+}
+
+// The items as a program, with jumps pointing at where their targets are now
+fn current(items: &[Item]) -> Vec<Instruction> {
+    items
+        .iter()
+        .map(|item| {
+            let mut instruction = item.instruction.clone();
+            if is_branch(&instruction) {
+                let target = relocate(items, instruction.a.data() as usize);
+                instruction.a = Oprand::Immediate(target.min(u8::MAX as usize) as u8);
+            }
+            instruction
+        })
+        .collect()
+}
+
+fn relocate(items: &[Item], address: usize) -> usize {
+    if let Some(position) = items.iter().position(|item| item.labels.contains(&address)) {
+        return position;
+    }
+    let old_length = items.iter().filter(|item| item.from.is_some()).count();
+    (address + items.len()).saturating_sub(old_length)
+}
+
+// Puts NOOPs before `reader`, unless that would break what's around it
+fn insert_noops(
+    items: &mut Vec<Item>,
+    program: &[Instruction],
+    pipeline: &Pipeline,
+    reader: usize,
+    noops: usize,
+) -> bool {
+    // The NOOPs would overwrite the accumalator, or take the place of
+    // something in a branch's delay slot
+    if alu_read(&program[reader]).is_some() || shadowing_branch(program, reader, pipeline).is_some()
+    {
+        return false;
+    }
+    let cause = items[reader].cause;
+    let mut added: Vec<Item> = (0..noops)
+        .map(|_| Item {
+            instruction: Instruction::none(),
+            from: None,
+            labels: vec![],
+            cause,
+        })
+        .collect();
+    // Jumps to the reader wait for the NOOPs too
+    added[0].labels = std::mem::take(&mut items[reader].labels);
+    items.splice(reader..reader, added);
+    true
+}
+
+// Whether the instruction at `moved` can run before the one at `to` without
+// changing what either of them, or anything between them, does
+fn can_move(program: &[Instruction], pipeline: &Pipeline, moved: usize, to: usize) -> bool {
+    let instruction = &program[moved];
+    if is_branch(instruction)
+        || alu_read(instruction).is_some()
+        || alu_read(&program[to]).is_some()
+        || shadowing_branch(program, to, pipeline).is_some()
+    {
+        return false;
+    }
+    // Jumping into the middle would skip the moved instruction
+    if program
+        .iter()
+        .filter(|instruction| is_branch(instruction))
+        .any(|branch| (to + 1..=moved).contains(&(branch.a.data() as usize)))
+    {
+        return false;
+    }
+    let reads = read_registers(instruction);
+    let writes = written_register(instruction);
+    // Whether the moved instruction has to stay after `between`
+    let depends = |between: &Instruction| {
+        let between_writes = written_register(between);
+        is_branch(between)
+            || between_writes.is_some_and(|register| reads.contains(&register))
+            || between_writes.is_some() && between_writes == writes
+            || writes.is_some_and(|register| read_registers(between).contains(&register))
+            // Ports are written in the order the program gives
+            || between.write_back() == WriteBack::Out && instruction.write_back() == WriteBack::Out
+    };
+    !program[to..moved].iter().any(depends)
+}
+
+// Tries to fix one hazard, returning whether anything changed
+fn fix(
+    items: &mut Vec<Item>,
+    program: &[Instruction],
+    pipeline: &Pipeline,
+    hazard: &Hazard,
+) -> bool {
+    match *hazard {
+        Hazard::Register { reader, noops, .. } => {
+            insert_noops(items, program, pipeline, reader, noops)
+        }
+        Hazard::Alu {
+            reader,
+            overwriter,
+            intended: Some(intended),
+            ..
+        } if overwriter + 1 == reader && can_move(program, pipeline, overwriter, intended) => {
+            let mut item = items.remove(overwriter);
+            // Jumps to the ALU instruction now start with the moved one
+            item.labels.append(&mut items[intended].labels);
+            items.insert(intended, item);
+            true
+        }
+        Hazard::Alu { .. } => false,
+    }
+}
+
+/// Adds NOOPs and moves instructions to fix the program's hazards, failing
+/// if the result doesn't fit in the machine's ROM.
+pub fn schedule(
+    instructions: &[Instruction],
+    pipeline: &Pipeline,
+    machine: &Machine,
+) -> Result<Schedule, ScheduleError> {
+    let mut items = items(instructions);
+    for _ in 0..MAX_FIXES {
+        let program = current(&items);
+        let hazards = find_hazards(&program, pipeline);
+        if !hazards
+            .iter()
+            .any(|hazard| fix(&mut items, &program, pipeline, hazard))
+        {
+            break;
+        }
+    }
+    finish(items, instructions.len(), machine, "Fixing hazards")
+}
+
+// Whether the instruction before the JMP at `branch` can run in its delay
+// slot instead
+fn can_fill_from_before(program: &[Instruction], pipeline: &Pipeline, branch: usize) -> bool {
+    let Some(before) = branch.checked_sub(1) else {
+        return false;
+    };
+    // BIE uses the flags the instruction before it sets, and the JMP
+    // overwrites the accumalator before the slot runs
+    program[branch].operation.definition().branch == Branch::Always
+        && !is_branch(&program[before])
+        && alu_read(&program[before]).is_none()
+        && shadowing_branch(program, before, pipeline).is_none()
+        // Jumping straight to the JMP would now run the moved instruction
+        && !program
+            .iter()
+            .any(|instruction| is_branch(instruction) && instruction.a.data() as usize == branch)
+}
+
+/// Fills the delay slots of branches that have instructions with side effects
+/// in them, failing if the result doesn't fit in the machine's ROM.
+pub fn fill_delay_slots(
+    instructions: &[Instruction],
+    pipeline: &Pipeline,
+    machine: &Machine,
+) -> Result<Schedule, ScheduleError> {
+    let slots = pipeline.delay_slots();
+    let mut items = items(instructions);
+    let mut branch = 0;
+    while branch < items.len() {
+        let program = current(&items);
+        if !is_branch(&program[branch])
+            || !shadow(&program, branch, pipeline).any(|slot| has_side_effects(&program[slot]))
+        {
+            branch += 1;
+            continue;
+        }
+        let mut fillers = vec![];
+        if can_fill_from_before(&program, pipeline, branch) {
+            let mut moved = items.remove(branch - 1);
+            branch -= 1;
+            // Jumps to the moved instruction go to the JMP, which runs it next
+            items[branch].labels.append(&mut moved.labels);
+            fillers.push(moved);
+        }
+        while fillers.len() < slots {
+            fillers.push(Item {
+                instruction: Instruction::none(),
+                from: None,
+                labels: vec![],
+                cause: items[branch].cause,
+            });
+        }
+        items.splice(branch + 1..branch + 1, fillers);
+        branch += slots + 1;
+    }
+    finish(items, instructions.len(), machine, "Filling delay slots")
+}
+
+fn items(instructions: &[Instruction]) -> Vec<Item> {
+    instructions
+        .iter()
+        .enumerate()
+        .map(|(address, instruction)| Item {
+            instruction: instruction.clone(),
+            from: Some(address),
+            labels: vec![address],
+            cause: address,
+        })
+        .collect()
+}
+
+// Turns the items back into a program, failing if it no longer fits in ROM.
+// `change` describes what was done to the program for the error.
+fn finish(
+    items: Vec<Item>,
+    old_length: usize,
+    machine: &Machine,
+    change: &str,
+) -> Result<Schedule, ScheduleError> {
+    let scheduled = current(&items);
+    if scheduled.len() > machine.rom_lines {
+        // The first added line that doesn't fit
+        let overflowing = items
+            .iter()
+            .filter(|item| item.from.is_none())
+            .nth(machine.rom_lines.saturating_sub(old_length));
+        return Err(ScheduleError {
+            address: overflowing.map_or(old_length.saturating_sub(1), |item| item.cause),
+            message: format!(
+                "{change} makes the program {} lines long, but ROM only has {} lines",
+                scheduled.len(),
+                machine.rom_lines
+            ),
+        });
+    }
+    if let Some(branch) = scheduled.iter().zip(&items).find(|(scheduled, item)| {
+        is_branch(scheduled)
+            && relocate(&items, item.instruction.a.data() as usize) >= machine.rom_lines
+    }) {
+        return Err(ScheduleError {
+            address: branch.1.cause,
+            message: format!(
+                "{change} moves the target of \"{}\" past the end of ROM",
+                branch.1.instruction
+            ),
+        });
+    }
+    Ok(Schedule {
+        from: items.iter().map(|item| item.from).collect(),
+        relocated: (0..old_length)
+            .map(|address| relocate(&items, address))
+            .collect(),
+        instructions: scheduled,
+    })
+}
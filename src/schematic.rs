@@ -0,0 +1,423 @@
+use crate::machine::Machine;
+use crate::nbt::Tag;
+use crate::parser::*;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+// Exports ROM images as Sponge schematics (version 2), which WorldEdit and
+// similar mods can paste into a world, and reads them back from Sponge and
+// Litematica schematics. Every bit of ROM is one block, `one` for a 1 and `zero`
+// for a 0, placed by a `RomLayout` describing how the ROM in the world is built.
+
+// Far bigger than any ROM, but stops a mistaken layout from using all the memory
+const MAX_BLOCKS: u64 = 1 << 24;
+
+/// Where each bit of ROM goes in the world. Positions are `[x, y, z]`, and the
+/// steps between bits, bytes and lines can point in any direction, so the same
+/// layout can describe a ROM facing any way.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RomLayout {
+    /// Where the first bit of the first byte of line 0 goes
+    pub origin: [i32; 3],
+    /// The step from one bit of a byte to the next
+    pub bit: [i32; 3],
+    /// The step from one byte of a line to the next
+    pub byte: [i32; 3],
+    /// The step from one line of ROM to the next
+    pub line: [i32; 3],
+    /// Whether the first bit of each byte is its most significant one
+    pub most_significant_first: bool,
+    /// The block placed for a 1
+    pub one: String,
+    /// The block placed for a 0
+    pub zero: String,
+    /// The Minecraft data version the schematic is saved for
+    pub data_version: i32,
+}
+
+impl Default for RomLayout {
+    fn default() -> Self {
+        Self {
+            origin: [0, 0, 0],
+            bit: [2, 0, 0],
+            byte: [0, 2, 0],
+            line: [0, 0, 2],
+            most_significant_first: true,
+            one: "minecraft:redstone_torch".to_string(),
+            zero: "minecraft:air".to_string(),
+            // Minecraft 1.20.1
+            data_version: 3465,
+        }
+    }
+}
+
+impl RomLayout {
+    /// Reads a layout from TOML. Anything left out keeps its default.
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
+        toml::from_str(toml).map_err(|error| error.to_string())
+    }
+
+    /// Where a bit of ROM is placed, counting bits from the least significant.
+    pub fn position(&self, line: usize, byte: usize, bit: usize) -> [i32; 3] {
+        let step = if self.most_significant_first {
+            7 - bit
+        } else {
+            bit
+        };
+        // Saturates rather than overflowing, so huge layouts are reported as too big
+        [0, 1, 2].map(|axis| {
+            self.origin[axis]
+                .saturating_add(self.line[axis].saturating_mul(line as i32))
+                .saturating_add(self.byte[axis].saturating_mul(byte as i32))
+                .saturating_add(self.bit[axis].saturating_mul(step as i32))
+        })
+    }
+
+    // Every bit of a ROM `lines` long with its position, as
+    // (line, byte, bit, position)
+    fn bits(&self, lines: usize) -> impl Iterator<Item = (usize, usize, usize, [i32; 3])> + '_ {
+        (0..lines).flat_map(move |line| {
+            (0..Instruction::SIZE).flat_map(move |byte| {
+                (0..8).map(move |bit| (line, byte, bit, self.position(line, byte, bit)))
+            })
+        })
+    }
+
+    // The lowest corner and the size of the box holding every bit
+    fn bounds(&self, lines: usize) -> ([i32; 3], [i32; 3]) {
+        let mut low = [i32::MAX; 3];
+        let mut high = [i32::MIN; 3];
+        for (_, _, _, position) in self.bits(lines) {
+            for axis in 0..3 {
+                low[axis] = low[axis].min(position[axis]);
+                high[axis] = high[axis].max(position[axis]);
+            }
+        }
+        let size = [0, 1, 2].map(|axis| high[axis].saturating_sub(low[axis]).saturating_add(1));
+        (low, size)
+    }
+
+    // Checks that every bit has a block of its own and the schematic isn't too big
+    fn check(&self, lines: usize) -> Result<(), String> {
+        let mut used = HashSet::new();
+        for (line, byte, bit, position) in self.bits(lines) {
+            if !used.insert(position) {
+                return Err(format!(
+                    "Bit {bit} of byte {byte} of line {line} is placed on a block already used \
+                     by another bit, at {position:?}"
+                ));
+            }
+        }
+        let (_, size) = self.bounds(lines);
+        let blocks: u64 = size.iter().map(|size| *size as u64).product();
+        if size.iter().any(|size| *size > i16::MAX as i32) || blocks > MAX_BLOCKS {
+            return Err(format!(
+                "The ROM is too big for a schematic, it is {size:?}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn write_varint(bytes: &mut Vec<i8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte as i8);
+            return;
+        }
+        bytes.push((byte | 0x80) as i8);
+    }
+}
+
+/// Builds the schematic of the ROM holding `program`.
+pub fn schematic(program: &Program, layout: &RomLayout) -> Result<Tag, String> {
+    let lines = program.machine.rom_lines;
+    layout.check(lines)?;
+    let rom = program.to_rom()?;
+    let (low, size) = layout.bounds(lines);
+    let [width, height, length] = size.map(|size| size as usize);
+    // Blocks are stored x first, then z, then y
+    let mut blocks = vec!["minecraft:air"; width * height * length];
+    for (line, byte, bit, position) in layout.bits(lines) {
+        let [x, y, z] = [0, 1, 2].map(|axis| (position[axis] - low[axis]) as usize);
+        let set = rom[line * Instruction::SIZE + byte] >> bit & 1 == 1;
+        blocks[x + z * width + y * width * length] = if set { &layout.one } else { &layout.zero };
+    }
+    let mut palette: HashMap<&str, usize> = HashMap::new();
+    let mut palette_order = vec![];
+    let mut block_data = vec![];
+    for block in blocks {
+        let index = *palette.entry(block).or_insert_with(|| {
+            palette_order.push(block);
+            palette_order.len() - 1
+        });
+        write_varint(&mut block_data, index);
+    }
+    let palette = palette_order
+        .iter()
+        .enumerate()
+        .map(|(index, block)| (block.to_string(), Tag::Int(index as i32)))
+        .collect();
+    Ok(Tag::Compound(vec![
+        ("Version".to_string(), Tag::Int(2)),
+        ("DataVersion".to_string(), Tag::Int(layout.data_version)),
+        ("Width".to_string(), Tag::Short(width as i16)),
+        ("Height".to_string(), Tag::Short(height as i16)),
+        ("Length".to_string(), Tag::Short(length as i16)),
+        ("Offset".to_string(), Tag::IntArray(low.to_vec())),
+        (
+            "PaletteMax".to_string(),
+            Tag::Int(palette_order.len() as i32),
+        ),
+        ("Palette".to_string(), Tag::Compound(palette)),
+        ("BlockData".to_string(), Tag::ByteArray(block_data)),
+        ("BlockEntities".to_string(), Tag::List(vec![])),
+    ]))
+}
+
+/// Writes the schematic of the ROM holding `program` as a gzipped `.schem` file.
+pub fn write_schematic(
+    program: &Program,
+    layout: &RomLayout,
+    writer: impl Write,
+) -> Result<(), String> {
+    let schematic = schematic(program, layout)?;
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    schematic
+        .write("Schematic", &mut encoder)
+        .and_then(|_| encoder.finish().map(|_| ()))
+        .map_err(|error: io::Error| error.to_string())
+}
+
+// A box of blocks read from a schematic
+struct Region {
+    // Where the region's lowest corner is, from the lowest corner of the schematic
+    low: [i32; 3],
+    size: [usize; 3],
+    palette: Vec<String>,
+    // Palette indexes, x first, then z, then y
+    blocks: Vec<usize>,
+}
+
+impl Region {
+    fn block(&self, position: [i32; 3]) -> Option<&str> {
+        let mut index = [0; 3];
+        for axis in 0..3 {
+            let offset = position[axis].checked_sub(self.low[axis])?;
+            let offset = usize::try_from(offset).ok()?;
+            if offset >= self.size[axis] {
+                return None;
+            }
+            index[axis] = offset;
+        }
+        let [x, y, z] = index;
+        let [width, _, length] = self.size;
+        let index = y
+            .checked_mul(length)?
+            .checked_add(z)?
+            .checked_mul(width)?
+            .checked_add(x)?;
+        let block = *self.blocks.get(index)?;
+        self.palette.get(block).map(String::as_str)
+    }
+}
+
+// How many blocks are in a box, or None if it is too big to count
+fn volume(size: [usize; 3]) -> Option<usize> {
+    size.iter()
+        .try_fold(1usize, |volume, size| volume.checked_mul(*size))
+}
+
+fn int(tag: Option<&Tag>, name: &str) -> Result<i32, String> {
+    match tag {
+        Some(Tag::Byte(value)) => Ok(*value as i32),
+        Some(Tag::Short(value)) => Ok(*value as i32),
+        Some(Tag::Int(value)) => Ok(*value),
+        _ => Err(format!("The schematic is missing its {name}")),
+    }
+}
+
+fn read_varints(bytes: &[i8]) -> Result<Vec<usize>, String> {
+    let mut values = vec![];
+    let (mut value, mut shift) = (0usize, 0);
+    for byte in bytes {
+        if shift > 28 {
+            return Err("The schematic's block data is corrupted".to_string());
+        }
+        value |= (*byte as u8 as usize & 0x7F) << shift;
+        shift += 7;
+        if *byte >= 0 {
+            values.push(value);
+            (value, shift) = (0, 0);
+        }
+    }
+    Ok(values)
+}
+
+// Reads a Sponge schematic, version 1, 2 or 3
+fn read_sponge(schematic: &Tag) -> Result<Region, String> {
+    // Version 3 moves the blocks into their own compound
+    let blocks = schematic.get("Blocks").unwrap_or(schematic);
+    let (Some(Tag::Compound(palette)), Some(Tag::ByteArray(data))) = (
+        blocks.get("Palette"),
+        blocks.get("BlockData").or(blocks.get("Data")),
+    ) else {
+        return Err("The schematic is missing its blocks".to_string());
+    };
+    let mut names = vec![String::new(); palette.len()];
+    for (name, index) in palette {
+        let index = int(Some(index), "palette")?;
+        match names.get_mut(index as usize) {
+            Some(slot) => *slot = name.clone(),
+            None => return Err(format!("{name} has an invalid palette index {index}")),
+        }
+    }
+    let size = [
+        int(schematic.get("Width"), "width")?,
+        int(schematic.get("Height"), "height")?,
+        int(schematic.get("Length"), "length")?,
+    ]
+    .map(|size| size.max(0) as usize);
+    let blocks = read_varints(data)?;
+    if volume(size) != Some(blocks.len()) {
+        return Err("The schematic has the wrong number of blocks".to_string());
+    }
+    Ok(Region {
+        low: [0; 3],
+        size,
+        palette: names,
+        blocks,
+    })
+}
+
+fn vector(tag: Option<&Tag>, name: &str) -> Result<[i32; 3], String> {
+    let tag = tag.ok_or_else(|| format!("The schematic is missing its {name}"))?;
+    Ok([
+        int(tag.get("x"), name)?,
+        int(tag.get("y"), name)?,
+        int(tag.get("z"), name)?,
+    ])
+}
+
+// Reads the regions of a Litematica schematic, with their corners relative to
+// the schematic's origin
+fn read_litematic(regions: &[(String, Tag)]) -> Result<Vec<Region>, String> {
+    let mut read = vec![];
+    for (name, region) in regions {
+        let position = vector(region.get("Position"), "region position")?;
+        let size = vector(region.get("Size"), "region size")?;
+        // Negative sizes grow from the position towards lower coordinates
+        let mut low = [0; 3];
+        for axis in 0..3 {
+            low[axis] = position[axis]
+                .checked_add(size[axis].min(0) + (size[axis] < 0) as i32)
+                .ok_or_else(|| format!("Region {name} has an invalid position"))?;
+        }
+        let size = size.map(|size| size.unsigned_abs() as usize);
+        let (Some(Tag::List(palette)), Some(Tag::LongArray(states))) =
+            (region.get("BlockStatePalette"), region.get("BlockStates"))
+        else {
+            return Err(format!("Region {name} is missing its blocks"));
+        };
+        let palette = palette
+            .iter()
+            .map(|state| match state.get("Name") {
+                Some(Tag::String(name)) => Ok(name.clone()),
+                _ => Err(format!("Region {name} has a block without a name")),
+            })
+            .collect::<Result<Vec<String>, String>>()?;
+        // Blocks are packed into as few bits as fit the palette, and can
+        // be split across two longs
+        let bits = (usize::BITS - palette.len().saturating_sub(1).leading_zeros()).max(2) as usize;
+        let mask = (1u64 << bits) - 1;
+        let wrong_size = || format!("Region {name} has the wrong number of blocks");
+        let volume = volume(size).ok_or_else(wrong_size)?;
+        if volume as u64 > MAX_BLOCKS || states.len() * 64 < volume * bits {
+            return Err(wrong_size());
+        }
+        let blocks = (0..volume)
+            .map(|index| {
+                let (long, offset) = (index * bits / 64, index * bits % 64);
+                let mut value = states[long] as u64 >> offset;
+                if offset + bits > 64 {
+                    value |= (states[long + 1] as u64) << (64 - offset);
+                }
+                (value & mask) as usize
+            })
+            .collect();
+        read.push(Region {
+            low,
+            size,
+            palette,
+            blocks,
+        });
+    }
+    // Make the corners relative to the lowest corner of every region
+    let low = [0, 1, 2].map(|axis| {
+        read.iter()
+            .map(|region| region.low[axis])
+            .min()
+            .unwrap_or(0)
+    });
+    for region in &mut read {
+        for (region_low, low) in region.low.iter_mut().zip(low) {
+            *region_low = region_low
+                .checked_sub(low)
+                .ok_or("The schematic's regions are too far apart")?;
+        }
+    }
+    Ok(read)
+}
+
+// Whether a block from a schematic is the block a layout expects. Block states
+// like "[lit=true]" are ignored unless the layout gives them.
+fn block_matches(found: &str, expected: &str) -> bool {
+    let without_state = |block: &str| block.split('[').next().unwrap_or_default().to_string();
+    found == expected || (!expected.contains('[') && without_state(found) == expected)
+}
+
+/// Reads the ROM image from a gzipped Sponge (`.schem`) or Litematica
+/// (`.litematic`) schematic of the ROM of `machine`, built with `layout`. The
+/// schematic's lowest corner is the lowest corner of the ROM, the same as in
+/// exported schematics.
+pub fn read_rom(
+    reader: impl io::Read,
+    layout: &RomLayout,
+    machine: &Machine,
+) -> Result<Vec<u8>, String> {
+    layout.check(machine.rom_lines)?;
+    let (_, root) = Tag::read(&mut GzDecoder::new(reader))
+        .map_err(|error| format!("Could not read the schematic: {error}"))?;
+    let regions = match (root.get("Regions"), root.get("Schematic")) {
+        (Some(Tag::Compound(regions)), _) => read_litematic(regions)?,
+        // Version 3 Sponge schematics are inside an unnamed root
+        (_, Some(schematic)) => vec![read_sponge(schematic)?],
+        _ => vec![read_sponge(&root)?],
+    };
+    let (low, _) = layout.bounds(machine.rom_lines);
+    let mut rom = vec![0; machine.rom_bytes()];
+    for (line, byte, bit, position) in layout.bits(machine.rom_lines) {
+        let in_schematic = [0, 1, 2].map(|axis| position[axis] - low[axis]);
+        let block = regions
+            .iter()
+            .find_map(|region| region.block(in_schematic))
+            .unwrap_or("minecraft:air");
+        if block_matches(block, &layout.one) {
+            rom[line * Instruction::SIZE + byte] |= 1 << bit;
+        } else if !block_matches(block, &layout.zero) {
+            return Err(format!(
+                "Bit {bit} of byte {byte} of line {line} at {position:?} is {block}, \
+                 not {} or {}",
+                layout.one, layout.zero
+            ));
+        }
+    }
+    Ok(rom)
+}
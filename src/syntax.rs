@@ -0,0 +1,174 @@
+use crate::parser::*;
+use std::fmt;
+
+// A concrete syntax tree of a source file. Unlike the assembler, which throws
+// away comments and spacing as it reads each line, it keeps every character,
+// so writing it back out gives exactly the text it was read from. Lines are
+// split into tokens the same way the assembler splits them, so oprands
+// written as expressions with spaces, like "loop + 2", are one token.
+//
+// Lines are read on their own, without expanding macros or includes, so the
+// tree can be read even when the program doesn't assemble.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Whitespace,
+    /// A label definition, including its colon
+    Label,
+    /// A directive's name, like `.equ`
+    Directive,
+    /// An instruction's, pseudo-instruction's or macro's name
+    Mnemonic,
+    /// An oprand, or a directive's argument
+    Oprand,
+    Comment,
+    /// Code that doesn't make sense, like a label with an invalid name
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    /// The column the token starts at, in bytes
+    pub column: usize,
+}
+
+/// One line of source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxLine {
+    pub tokens: Vec<Token>,
+    /// The line break after the line, "\n", "\r\n", or "" for the last line
+    pub ending: String,
+}
+
+/// A whole source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTree {
+    pub lines: Vec<SyntaxLine>,
+}
+
+impl SyntaxTree {
+    pub fn parse(source: &str) -> Self {
+        let lines = source
+            .split_inclusive('\n')
+            .map(|line| {
+                let text = line.trim_end_matches(['\n', '\r']);
+                SyntaxLine {
+                    tokens: tokenize(text),
+                    ending: line[text.len()..].to_string(),
+                }
+            })
+            .collect();
+        SyntaxTree { lines }
+    }
+}
+
+impl SyntaxLine {
+    fn find(&self, kind: TokenKind) -> Option<&Token> {
+        self.tokens.iter().find(|token| token.kind == kind)
+    }
+
+    /// The label defined on the line, without its colon.
+    pub fn label(&self) -> Option<&str> {
+        self.find(TokenKind::Label)
+            .map(|token| token.text.trim_end_matches(':').trim_end())
+    }
+
+    /// The directive or mnemonic the line's code starts with.
+    pub fn name(&self) -> Option<&Token> {
+        self.tokens
+            .iter()
+            .find(|token| matches!(token.kind, TokenKind::Directive | TokenKind::Mnemonic))
+    }
+
+    pub fn oprands(&self) -> impl Iterator<Item = &str> {
+        self.tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::Oprand)
+            .map(|token| token.text.as_str())
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.find(TokenKind::Comment)
+            .map(|token| token.text.as_str())
+    }
+
+    /// Whether the line is empty or only whitespace.
+    pub fn is_blank(&self) -> bool {
+        self.tokens
+            .iter()
+            .all(|token| token.kind == TokenKind::Whitespace)
+    }
+}
+
+impl fmt::Display for SyntaxLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            write!(f, "{}", token.text)?;
+        }
+        write!(f, "{}", self.ending)
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            write!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+// Splits a line into tokens, with whitespace tokens between the others so
+// that none of the line is lost
+fn tokenize(line: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut push = |kind, start: usize, end: usize| {
+        if start < end {
+            tokens.push(Token {
+                kind,
+                text: line[start..end].to_string(),
+                column: start,
+            });
+        }
+    };
+    let code = strip_comment(line);
+    let mut start = 0;
+    // Directives like `.include "c:/file.elt"` can have colons in them
+    let is_directive = code.trim_start().starts_with('.');
+    if let Some(colon) = find_unquoted(code, ':').filter(|_| !is_directive) {
+        let label_start = code.len() - code.trim_start().len();
+        let name = code[..colon].trim().to_ascii_uppercase();
+        // Only one label can be defined on a line
+        let another = find_unquoted(&code[colon + 1..], ':').is_some();
+        if is_label_name(&name) && !another {
+            push(TokenKind::Whitespace, 0, label_start);
+            push(TokenKind::Label, label_start, colon + 1);
+            start = colon + 1;
+        } else {
+            let end = code.trim_end().len();
+            push(TokenKind::Whitespace, 0, label_start);
+            push(TokenKind::Unknown, label_start, end);
+            start = end;
+        }
+    }
+    let rest = &code[start..];
+    let words = join_expressions(rest, split_words(rest));
+    let mut position = start;
+    for (index, (span, _)) in words.iter().enumerate() {
+        let kind = match index {
+            0 if rest[span.column..].starts_with('.') => TokenKind::Directive,
+            0 => TokenKind::Mnemonic,
+            _ => TokenKind::Oprand,
+        };
+        // Spans are from the start of the rest of the line
+        let word_start = start + span.column;
+        push(TokenKind::Whitespace, position, word_start);
+        push(kind, word_start, word_start + span.length);
+        position = word_start + span.length;
+    }
+    push(TokenKind::Whitespace, position, code.len());
+    push(TokenKind::Comment, code.len(), line.len());
+    tokens
+}
@@ -0,0 +1,85 @@
+// Helpers shared by the tests. Each test file is built on its own and only
+// uses some of them.
+#![allow(dead_code)]
+
+use electron::diagnostic::Diagnostic;
+use electron::emulator::Emulator;
+use electron::parser::*;
+use std::path::PathBuf;
+
+// Small xorshift generator so the tests are reproducible without extra crates
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, max: u64) -> u64 {
+        self.next() % max
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+// Assembles a program that has to assemble
+pub fn assemble(source: &str) -> Program {
+    assemble_with(source, AssemblyOptions::default())
+}
+
+pub fn assemble_with(source: &str, options: AssemblyOptions) -> Program {
+    ProgramLoader::assemble_file_with("program.elt", source, &options)
+        .unwrap_or_else(|errors| panic!("{source}\nfailed to assemble: {errors:?}"))
+}
+
+// The errors, without the warnings, from a program that fails to assemble
+pub fn assembly_errors(source: &str) -> Vec<Diagnostic> {
+    let diagnostics = ProgramLoader::assemble(source).unwrap_err();
+    diagnostics
+        .into_iter()
+        .filter(Diagnostic::is_error)
+        .collect()
+}
+
+// The instructions as they would be written
+pub fn code(instructions: &[Instruction]) -> Vec<String> {
+    instructions.iter().map(Instruction::to_string).collect()
+}
+
+// Runs a program long enough to reach its end, returning what it wrote to ports
+pub fn run(program: Program) -> Vec<u8> {
+    let mut emulator = Emulator::new(program);
+    for _ in 0..64 {
+        emulator.clock();
+    }
+    emulator.ports.out
+}
+
+// The message of every warning about a program
+pub fn messages(program: &Program) -> Vec<&str> {
+    program
+        .warnings
+        .iter()
+        .map(|warning| warning.message.as_str())
+        .collect()
+}
+
+pub fn warnings(source: &str) -> Vec<String> {
+    messages(&assemble(source))
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+// An empty directory of its own for a test to write files in
+pub fn temp_dir(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("electron-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
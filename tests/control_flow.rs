@@ -0,0 +1,75 @@
+use electron::control_flow::*;
+use electron::parser::*;
+
+mod common;
+use common::*;
+
+fn edges(block: &BasicBlock) -> Vec<(usize, EdgeKind)> {
+    block
+        .edges
+        .iter()
+        .map(|edge| (edge.to, edge.kind))
+        .collect()
+}
+
+#[test]
+fn blocks_end_after_delay_slots() {
+    let source = "IMM R1 3\nloop: NOT R0 R0\nUADD R1 R1\nBIE done\nOUT %0 R1\nJMP loop\nNOOP\ndone: OUT %1 R1";
+    let graph = ProgramLoader::assemble(source)
+        .unwrap()
+        .control_flow_graph();
+    let blocks: Vec<_> = graph.blocks.iter().map(|b| b.addresses.clone()).collect();
+    assert_eq!(blocks, [0..1, 1..5, 5..7, 7..8]);
+    assert_eq!(edges(&graph.blocks[0]), [(1, EdgeKind::Next)]);
+    assert_eq!(
+        edges(&graph.blocks[1]),
+        [(3, EdgeKind::Taken), (2, EdgeKind::Next)]
+    );
+    assert_eq!(edges(&graph.blocks[2]), [(1, EdgeKind::Jump)]);
+    // Running off the end runs empty ROM until the program counter wraps
+    assert_eq!(edges(&graph.blocks[3]), [(0, EdgeKind::Wrap)]);
+    assert_eq!(graph.block_of(6), Some(2));
+    assert_eq!(graph.reachable(), [true; 4]);
+    assert!(graph.endless_loops().is_empty());
+}
+
+#[test]
+fn dot_shows_every_block_and_edge() {
+    let program = ProgramLoader::assemble("loop: IMM R1 1\nJMP loop\nNOOP\nOUT %0 R1").unwrap();
+    let dot = program.control_flow_graph().to_dot(&program.instructions);
+    assert_eq!(
+        dot,
+        "digraph program {
+    node [shape=box, fontname=\"monospace\"];
+    b0 [label=\"00  IMM R1 1\\l01  JMP 0\\l02  NOOP\\l\"];
+    b1 [label=\"03  OUT %0 R1\\l\", color=grey, fontcolor=grey];
+    b0 -> b0 [label=\"jump\"];
+    b1 -> b0 [label=\"wrap\", style=dashed];
+}
+"
+    );
+}
+
+#[test]
+fn unreachable_lines_are_warned_about() {
+    let found = warnings("loop: IMM R1 1\nOUT %0 R1\nJMP loop\nNOOP\nIMM R2 2\nOUT %1 R2");
+    assert_eq!(found, ["\"IMM R2 2\" can never be run"]);
+    assert!(warnings("IMM R1 1\nCMP R1 R0\nBIE 5\nNOOP\nOUT %0 R1\nOUT %1 R1").is_empty());
+}
+
+#[test]
+fn loops_without_out_are_warned_about() {
+    let found = warnings("IMM R1 1\nloop: SADD R1 R1\nJMP loop\nNOOP");
+    assert_eq!(
+        found,
+        ["\"JMP 1\" loops forever without an OUT, so the ports never change again"]
+    );
+    // Stopping on purpose, leaving the loop, or going round by wrapping
+    assert!(warnings("IMM R1 1\nOUT %0 R1\ndone: JMP done\nNOOP").is_empty());
+    assert!(warnings(
+        "IMM R1 1\nloop: SADD R1 R1\nCMP R1 R0\nBIE done\nNOOP\nJMP loop\nNOOP\ndone: OUT %0 R1"
+    )
+    .is_empty());
+    let found = warnings("IMM R1 1\nSADD R1 R1");
+    assert!(!found.iter().any(|warning| warning.contains("loops")));
+}
@@ -0,0 +1,55 @@
+use electron::dataflow::*;
+use electron::hazards::Pipeline;
+use electron::parser::*;
+
+fn issues(source: &str) -> Vec<RegisterIssue> {
+    let program = ProgramLoader::assemble(source).unwrap();
+    find_register_issues(&program.instructions, &Pipeline::EMULATOR)
+}
+
+#[test]
+fn registers_read_before_being_written_on_any_path_are_found() {
+    // R2 is only written when the branch isn't taken
+    let source = "IMM R1 1\nCMP R1 R1\nBIE show\nNOOP\nIMM R2 5\nshow: OUT %0 R2\nOUT %1 R1";
+    assert_eq!(
+        issues(source),
+        [RegisterIssue::Uninitialized {
+            reader: 5,
+            register: 2
+        }]
+    );
+    let program = ProgramLoader::assemble(source).unwrap();
+    let warning = &program.warnings[0];
+    assert_eq!(
+        warning.message,
+        "\"OUT %0 R2\" may read R2 before anything has written it"
+    );
+    // Points at the register
+    assert_eq!((warning.line, warning.column), (5, 13));
+}
+
+#[test]
+fn values_nothing_reads_are_found() {
+    let source = "loop: IMM R1 1\nIMM R1 2\nIMM R2 3\nOUT %0 R1\nJMP loop\nNOOP";
+    assert_eq!(
+        issues(source),
+        [
+            RegisterIssue::DeadWrite {
+                writer: 0,
+                register: 1
+            },
+            RegisterIssue::DeadWrite {
+                writer: 2,
+                register: 2
+            }
+        ]
+    );
+}
+
+#[test]
+fn values_read_after_going_round_a_loop_are_wanted() {
+    let source = "IMM R1 0\nIMM R2 1\nloop: SADD R1 R2\nOUT %0 R1\nJMP loop\nNOOP";
+    assert!(issues(source).is_empty());
+    let program = ProgramLoader::assemble(source).unwrap();
+    assert!(program.warnings.is_empty(), "{:?}", program.warnings);
+}
@@ -0,0 +1,156 @@
+use electron::hazards::*;
+use electron::parser::*;
+use electron::schedule::*;
+
+mod common;
+use common::*;
+
+fn filled(source: &str) -> Program {
+    let options = AssemblyOptions {
+        fill_delay_slots: true,
+        ..AssemblyOptions::default()
+    };
+    assemble_with(source, options)
+}
+
+const LOOP: &str = "IMM R1 1
+loop:
+SADD R1 R1
+OUT %0 R1
+JMP loop
+OUT %1 R1
+CMP R1 R0
+BIE loop
+IMM R2 3
+OUT %2 R2";
+
+// Warnings about unreachable lines are tested in tests/control_flow.rs
+fn delay_slot_warnings(program: &Program) -> Vec<&str> {
+    messages(program)
+        .into_iter()
+        .filter(|message| !message.ends_with("can never be run"))
+        .collect()
+}
+
+#[test]
+fn side_effects_in_delay_slots_are_warned_about() {
+    let program = assemble(LOOP);
+    let found = delay_slot_warnings(&program);
+    assert_eq!(found.len(), 2, "{found:?}");
+    assert_eq!(
+        found[0],
+        "\"OUT %1 R1\" is in the delay slot of \"JMP 1\" on line 5, so it runs before the program jumps"
+    );
+    assert!(found[1].ends_with("whether or not the branch is taken"));
+    assert!(assemble("IMM R1 1\nloop: OUT %0 R1\nJMP loop\nNOOP")
+        .warnings
+        .is_empty());
+}
+
+#[test]
+fn filled_delay_slots_only_run_what_was_written_first() {
+    let program = filled(LOOP);
+    assert!(
+        delay_slot_warnings(&program).is_empty(),
+        "{:?}",
+        program.warnings
+    );
+    assert_eq!(
+        code(&program.instructions),
+        [
+            "IMM R1 1",
+            "SADD R1 R1",
+            "JMP 1",
+            "OUT %0 R1",
+            "OUT %1 R1",
+            "ADD R1 R0",
+            "BIE 1",
+            "NOOP",
+            "IMM R2 3",
+            "OUT %2 R2"
+        ]
+    );
+    assert_eq!(program.symbols[0].value, "1");
+    assert!(program.instructions[7].source.is_none());
+}
+
+#[test]
+fn lines_moved_out_of_a_jumps_delay_slot_never_run() {
+    let source = "IMM R1 1
+loop:
+SADD R1 R1
+CMP R1 R0
+BIE done
+OUT %0 R1
+OUT %1 R1
+JMP loop
+OUT %2 R1
+done:
+IMM R2 3
+OUT %3 R2";
+    let program = filled(source);
+    assert_eq!(
+        code(&program.instructions),
+        [
+            "IMM R1 1",
+            "SADD R1 R1",
+            "ADD R1 R0",
+            "BIE 9",
+            "NOOP",
+            "OUT %0 R1",
+            "JMP 1",
+            "OUT %1 R1",
+            "OUT %2 R1",
+            "IMM R2 3",
+            "OUT %3 R2"
+        ]
+    );
+    assert_eq!(program.symbols[0].value, "9");
+    assert!(program.instructions[4].source.is_none());
+    assert_eq!(messages(&program), ["\"OUT %2 R1\" can never be run"]);
+}
+
+#[test]
+fn taken_branches_skip_filled_slots() {
+    let source = "IMM R1 1\nCMP R1 R1\nBIE end\nIMM R2 9\nend: OUT %0 R2";
+    assert_eq!(run(assemble(source))[0], 9);
+    assert_eq!(run(filled(source))[0], 0);
+}
+
+#[test]
+fn slower_pipelines_have_more_delay_slots() {
+    let pipeline = Pipeline {
+        later_stages_first: false,
+        ..Pipeline::EMULATOR
+    };
+    assert_eq!(Pipeline::EMULATOR.delay_slots(), 1);
+    assert_eq!(pipeline.delay_slots(), 2);
+    let program = assemble("loop: IMM R1 1\nJMP loop\nOUT %0 R1\nOUT %1 R1");
+    assert_eq!(shadow(&program.instructions, 1, &pipeline), 2..4);
+    assert_eq!(
+        shadowing_branch(&program.instructions, 3, &pipeline),
+        Some(1)
+    );
+    let filled = fill_delay_slots(&program.instructions, &pipeline, &program.machine).unwrap();
+    assert_eq!(
+        code(&filled.instructions),
+        ["JMP 0", "IMM R1 1", "NOOP", "OUT %0 R1", "OUT %1 R1"]
+    );
+}
+
+#[test]
+fn overflowing_rom_is_blamed_on_the_branch() {
+    let source = format!(
+        "IMM R1 1\n{}CMP R1 R1\nBIE 0\nOUT %1 R1",
+        "OUT %0 R1\n".repeat(28)
+    );
+    let options = AssemblyOptions {
+        fill_delay_slots: true,
+        ..AssemblyOptions::default()
+    };
+    let diagnostics =
+        ProgramLoader::assemble_file_with("program.elt", &source, &options).unwrap_err();
+    let error = &diagnostics[0];
+    assert!(error.message.contains("33 lines"), "{diagnostics:?}");
+    assert_eq!(error.line, 30);
+}
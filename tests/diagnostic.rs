@@ -0,0 +1,77 @@
+use electron::diagnostic::*;
+use electron::parser::*;
+
+#[test]
+fn diagnostics_are_rendered_like_rustc() {
+    let source = "IMM R1 1\nADDD R1 R1\nOUT %0 R1";
+    let diagnostics = ProgramLoader::assemble_file("example.elt", source).unwrap_err();
+    let error = diagnostics.iter().find(|error| error.is_error()).unwrap();
+    assert_eq!(
+        error.to_string(),
+        "2:1: error: \"ADDD\" is not a valid instruction"
+    );
+    assert_eq!(
+        error.render("example.elt"),
+        "error: \"ADDD\" is not a valid instruction
+ --> example.elt:2:1
+  |
+2 | ADDD R1 R1
+  | ^^^^
+  = help: did you mean ADD?
+"
+    );
+}
+
+#[test]
+fn carets_line_up_after_tabs_and_wide_characters() {
+    let tab = Diagnostic::warning(9, 5, "a tab")
+        .with_length(2)
+        .with_line_text("\tADD R1 ; ü".to_string());
+    assert_eq!(
+        tab.render("tabs.elt"),
+        "warning: a tab
+  --> tabs.elt:10:6
+   |
+10 | \tADD R1 ; ü
+   | \t    ^^
+"
+    );
+    // "ÄÖ: " is 6 bytes but 4 characters wide
+    let wide = Diagnostic::error(10, 6, "wide")
+        .with_length(3)
+        .with_line_text("ÄÖ: JMP".to_string());
+    assert_eq!(
+        wide.render("wide.elt"),
+        "error: wide
+  --> wide.elt:11:7
+   |
+11 | ÄÖ: JMP
+   |     ^^^
+"
+    );
+    // "Ré" is 3 bytes but 2 characters wide
+    let token = Diagnostic::error(0, 4, "wide token")
+        .with_length(3)
+        .with_line_text("IMM Ré 1".to_string());
+    assert_eq!(
+        token.render("token.elt"),
+        "error: wide token
+ --> token.elt:1:5
+  |
+1 | IMM Ré 1
+  |     ^^
+"
+    );
+}
+
+#[test]
+fn misspellings_are_matched_to_close_words() {
+    let words = ["ADD", "SADD", "JMP", "LOOP_START"];
+    assert_eq!(closest_match("ADDD", words), Some("ADD"));
+    assert_eq!(closest_match("JMPP", words), Some("JMP"));
+    assert_eq!(closest_match("LOOP_STRAT", words), Some("LOOP_START"));
+    // Longer words can be further off, but not by more than a third
+    assert_eq!(closest_match("LOOPSTRT", words), Some("LOOP_START"));
+    assert_eq!(closest_match("OUT", words), None);
+    assert_eq!(closest_match("ADD", []), None);
+}
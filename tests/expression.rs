@@ -0,0 +1,124 @@
+use electron::expression::*;
+use electron::parser::*;
+
+mod common;
+use common::*;
+
+// The first error from assembling a program that fails to assemble
+fn first_error(source: &str) -> String {
+    let diagnostics = ProgramLoader::assemble(source).unwrap_err();
+    let error = diagnostics.iter().find(|diagnostic| diagnostic.is_error());
+    error.unwrap().message.clone()
+}
+
+#[test]
+fn literals_are_read_in_every_base() {
+    for (literal, value) in [
+        ("42", 42),
+        ("1_000", 1000),
+        ("0x1F", 31),
+        ("0XfF", 255),
+        ("$ff", 255),
+        ("0o17", 15),
+        ("B101", 5),
+        ("0b1_0", 2),
+        ("-5", -5),
+        ("-0x10", -16),
+        ("'A'", 65),
+        ("';'", 59),
+        ("'\\n'", 10),
+        ("'\\''", 39),
+        ("'\\\\'", 92),
+    ] {
+        assert_eq!(parse_number(literal), Ok(value), "{literal}");
+    }
+    for literal in ["--5", "-", "0x", "0xG", "B2", "0o8", "'ab'", "'é'", "'A"] {
+        assert!(parse_number(literal).is_err(), "{literal}");
+    }
+}
+
+#[test]
+fn literals_assemble_to_their_value() {
+    let program =
+        assemble("IMM R1 0x1F\nIMM R2 -1\nIMM R3 'a'\nIMM R4 ';' ; a semicolon\nIMM R5 -128");
+    assert_eq!(
+        code(&program.instructions),
+        [
+            "IMM R1 31",
+            "IMM R2 255",
+            "IMM R3 97",
+            "IMM R4 59",
+            "IMM R5 128"
+        ]
+    );
+    assert_eq!(
+        first_error("IMM R1 --5"),
+        "\"--5\" has two minus signs in a row"
+    );
+    assert_eq!(
+        first_error("IMM R1 -129"),
+        "\"-129\" does not fit in 8 bits (-128 to 255)"
+    );
+}
+
+#[test]
+fn expressions_follow_c_precedence() {
+    let lookup = |name: &str| (name == "WIDTH").then_some(8);
+    for (expression, value) in [
+        ("1 + 2 * 3", 7),
+        ("(1 + 2) * 3", 9),
+        ("10 - 4 - 3", 3),
+        ("100 / 7 % 4", 2),
+        ("1 << 2 + 1", 8),
+        ("6 & 3 | 8", 10),
+        ("1 | 6 ^ 3", 5),
+        ("-2 * 3", -6),
+        ("5 - -3", 8),
+        ("~0", -1),
+        ("((WIDTH))-1", 7),
+        ("'A' + 1", 66),
+    ] {
+        assert_eq!(evaluate(expression, lookup), Ok(value), "{expression}");
+    }
+}
+
+#[test]
+fn bad_expressions_are_errors() {
+    let lookup = |_: &str| None;
+    for (expression, error) in [
+        ("1 / 0", "Division by zero in \"1 / 0\""),
+        ("5 % (2 - 2)", "Division by zero in \"5 % (2 - 2)\""),
+        (
+            "0x7FFF_FFFF_FFFF_FFFF + 1",
+            "\"0x7FFF_FFFF_FFFF_FFFF + 1\" is too large to calculate",
+        ),
+        ("1 << 64", "Can't shift by 64 in \"1 << 64\""),
+        ("(1 + 2", "\"(1 + 2\" is missing a \")\""),
+        ("1 +", "\"1 +\" ends unexpectedly"),
+        ("1 2", "Unexpected \"2\" in \"1 2\""),
+        ("--1", "\"--1\" has two minus signs in a row"),
+        ("WIDTH", "\"WIDTH\" is not defined"),
+    ] {
+        assert_eq!(evaluate(expression, lookup), Err(error.to_string()));
+    }
+}
+
+#[test]
+fn oprands_can_be_expressions() {
+    let program = assemble(".equ WIDTH 3\nIMM R(WIDTH - 1) (WIDTH + 1) * 2\nJMP 31");
+    assert_eq!(code(&program.instructions), ["IMM R2 8", "JMP 31"]);
+    assert_eq!(
+        first_error("JMP 16 * 2"),
+        "Jump target 32 is past the end of ROM (32 lines)"
+    );
+    let diagnostics = ProgramLoader::assemble("IMM R1 - 1").unwrap_err();
+    assert_eq!(
+        diagnostics[0].message,
+        "IMM is missing oprand B, \"R1 - 1\" was read as one oprand"
+    );
+    assert_eq!((diagnostics[0].column, diagnostics[0].length), (4, 6));
+    assert_eq!(
+        diagnostics[0].help.as_deref(),
+        Some("write negative numbers without a space after the \"-\"")
+    );
+}
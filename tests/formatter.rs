@@ -0,0 +1,66 @@
+use electron::formatter::format;
+use electron::parser::*;
+use electron::syntax::*;
+
+const MESSY: &str = "; Counts up
+.EQU width $0A   ; the width
+  imm r1 width - 1
+loop: sadd r1 r2 ; add
+\tout %0 r1
+
+
+  bie loop
+  jmp  loop  ; again
+  noop
+";
+
+#[test]
+fn syntax_trees_keep_every_character() {
+    let source = "\tloop :  IMM R1 'a' ; it's ';'\r\n\n.include \"c:/lib.elt\"\n1bad: NOOP\r\n   ";
+    let tree = SyntaxTree::parse(source);
+    assert_eq!(tree.to_string(), source);
+    assert_eq!(tree.lines.len(), 5);
+    let line = &tree.lines[0];
+    assert_eq!(line.label(), Some("loop"));
+    assert_eq!(line.name().unwrap().text, "IMM");
+    assert_eq!(line.oprands().collect::<Vec<_>>(), ["R1", "'a'"]);
+    assert_eq!(line.comment(), Some("; it's ';'"));
+    assert_eq!(line.ending, "\r\n");
+    assert!(tree.lines[1].is_blank());
+    assert_eq!(tree.lines[2].label(), None);
+    assert_eq!(tree.lines[3].tokens[0].kind, TokenKind::Unknown);
+}
+
+#[test]
+fn programs_are_formatted_the_same_way() {
+    assert_eq!(
+        format(MESSY),
+        "; Counts up
+.equ width 0x0A ; the width
+    IMM  R1 width - 1
+loop:
+    SADD R1 R2  ; add
+    OUT  %0 R1
+
+    BIE loop
+    JMP loop ; again
+    NOOP
+"
+    );
+}
+
+#[test]
+fn formatting_twice_changes_nothing_more() {
+    let formatted = format(MESSY);
+    assert_eq!(format(&formatted), formatted);
+    assert_eq!(format(""), "");
+}
+
+#[test]
+fn formatting_keeps_what_the_program_means() {
+    let source = "IMM r1 B0000_1111\nimm R2 $f\nloop: SADD r1 R2\nOUT %0 R1\nJMP loop\nNOOP";
+    let before = ProgramLoader::assemble(source).unwrap();
+    let after = ProgramLoader::assemble(&format(source)).unwrap();
+    assert_eq!(before.to_rom().unwrap(), after.to_rom().unwrap());
+    assert!(format(source).contains("IMM  R1 0b0000_1111\n    IMM  R2 0xF"));
+}
@@ -0,0 +1,80 @@
+use electron::emulator::Emulator;
+use electron::hazards::*;
+use electron::parser::*;
+
+mod common;
+use common::*;
+
+// Warnings about how registers are used are tested in tests/dataflow.rs, ones
+// about delay slots in tests/delay_slots.rs, and endless loops in
+// tests/control_flow.rs
+fn hazard_warnings(source: &str) -> Vec<String> {
+    warnings(source)
+        .into_iter()
+        .filter(|message| {
+            !message.ends_with("before anything has written it")
+                && !message.ends_with("nothing reads that value")
+                && !message.contains("in the delay slot of")
+                && !message.contains("loops forever")
+        })
+        .collect()
+}
+
+#[test]
+fn registers_are_written_before_the_next_instruction_reads_them() {
+    let source = "IMM R1 5\nSADD R1 R1\nOUT %0 R1";
+    assert!(hazard_warnings(source).is_empty());
+    let mut emulator = Emulator::new(assemble(source));
+    for _ in 0..8 {
+        emulator.clock();
+    }
+    assert_eq!(emulator.ports.out[0], 10);
+}
+
+#[test]
+fn slower_pipelines_need_noops() {
+    let pipeline = Pipeline {
+        later_stages_first: false,
+        ..Pipeline::EMULATOR
+    };
+    assert_eq!(pipeline.register_distance(), 2);
+    let program = ProgramLoader::assemble("IMM R1 5\nSADD R2 R1\nSADD R3 R1").unwrap();
+    assert_eq!(
+        find_hazards(&program.instructions, &pipeline),
+        [Hazard::Register {
+            reader: 1,
+            writer: 0,
+            register: 1,
+            noops: 1
+        }]
+    );
+}
+
+#[test]
+fn overwritten_accumalator_and_flags_are_warned_about() {
+    let found = hazard_warnings("ADD R1 R2\nIMM R3 1\nUADD R4 R0\nXADD R1\nNOOP\nBIE 0");
+    assert_eq!(found.len(), 2, "{found:?}");
+    assert!(found[0].contains("\"IMM R3 1\" on line 2"), "{found:?}");
+    assert!(
+        found[1].starts_with("\"BIE 0\" uses the flags"),
+        "{found:?}"
+    );
+    let source = "CMP R1 R2\nBIE 4\nSADD R1 R1\nJMP 0\nNOT R0 R1\nUADDC R1 R0";
+    assert!(hazard_warnings(source).is_empty());
+}
+
+#[test]
+fn branch_delay_slots_run_before_the_target() {
+    let program =
+        ProgramLoader::assemble("NOT R0 R1\nloop: UADD R1 R1\nJMP loop\nIMM R2 1").unwrap();
+    let hazards = find_hazards(&program.instructions, &Pipeline::EMULATOR);
+    assert_eq!(
+        hazards,
+        [Hazard::Alu {
+            reader: 1,
+            overwriter: 3,
+            intended: Some(1),
+            value: AluValue::Accumalator
+        }]
+    );
+}
@@ -0,0 +1,102 @@
+use electron::emulator::*;
+use electron::hazards::*;
+use electron::isa::*;
+use electron::machine::Machine;
+use electron::parser::*;
+
+// Every way each instruction can be written, with R3 or %3 for A and R2 or 5
+// for B
+fn every_instruction() -> Vec<String> {
+    let mut sources = vec![];
+    for definition in &INSTRUCTION_SET {
+        let mut args = vec![OperationArgs::None];
+        if definition.alu_arguments {
+            args.extend([OperationArgs::S, OperationArgs::U, OperationArgs::X]);
+        }
+        for args in args {
+            let source = definition
+                .usage(&args)
+                .replace("RA", "R3")
+                .replace("%A", "%3")
+                .replace("RB", "R2")
+                .replace(" A", " 3")
+                .replace(" B", " 5");
+            sources.push(source);
+        }
+    }
+    sources
+}
+
+#[test]
+fn the_readme_lists_every_instruction() {
+    let readme = include_str!("../README.md").replace("\r\n", "\n");
+    assert!(
+        readme.contains(&reference()),
+        "Copy this table into the README:\n{}",
+        reference()
+    );
+}
+
+#[test]
+fn every_instruction_is_written_read_and_stored_the_same_way() {
+    let mut opcodes = vec![];
+    for definition in &INSTRUCTION_SET {
+        let operation = &definition.operation;
+        assert_eq!(operation.definition().mnemonic, definition.mnemonic);
+        assert_eq!(
+            Operation::from_opcode(definition.opcode).as_ref(),
+            Some(operation)
+        );
+        assert_eq!(
+            Operation::from_mnemonic(definition.mnemonic).as_ref(),
+            Some(operation)
+        );
+        for alias in definition.aliases {
+            assert_eq!(Operation::from_mnemonic(alias).as_ref(), Some(operation));
+        }
+        assert!(!opcodes.contains(&definition.opcode));
+        opcodes.push(definition.opcode);
+    }
+    for source in every_instruction() {
+        let program = ProgramLoader::assemble(&source).unwrap();
+        let instruction = &program.instructions[0];
+        assert_eq!(instruction.to_string(), source);
+        let decoded = Instruction::decode(instruction.encode()).unwrap();
+        assert_eq!(decoded.to_string(), source);
+    }
+}
+
+#[test]
+fn the_emulator_does_what_the_hazard_checks_expect() {
+    for source in every_instruction() {
+        // Give every register a different value, then run the instruction
+        // and wait for it to write back
+        let registers = Machine::default().registers;
+        let mut program = String::new();
+        for register in 1..registers {
+            program += &format!("IMM R{register} {}\n", register * 10);
+        }
+        program += &source;
+        let program = ProgramLoader::assemble(&program).unwrap();
+        let instruction = program.instructions.last().unwrap().clone();
+        let mut emulator = Emulator::new(program);
+        for _ in 0..registers - 1 + 3 {
+            emulator.clock();
+        }
+        let before: Vec<u8> = (1..registers as u8)
+            .map(|register| emulator.registers.read(register))
+            .collect();
+        emulator.clock();
+        let changed: Vec<u8> = (1..registers as u8)
+            .filter(|register| emulator.registers.read(*register) != before[*register as usize - 1])
+            .collect();
+        let expected: Vec<u8> = written_register(&instruction).into_iter().collect();
+        assert_eq!(changed, expected, "{source}");
+        let outputs = emulator.ports.out.iter().any(|port| *port != 0);
+        assert_eq!(
+            outputs,
+            instruction.write_back() == WriteBack::Out,
+            "{source}"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use electron::language_server::run;
+use serde_json::{json, Value};
+use std::io::Cursor;
+
+const URI: &str = "file:///programs/count.elt";
+
+// Runs the server on a script of messages, returning everything it sent back
+fn talk(messages: &[Value]) -> Vec<Value> {
+    let mut input = vec![];
+    for message in messages {
+        let body = message.to_string();
+        input.extend(format!("Content-Length: {}\r\n\r\n{body}", body.len()).bytes());
+    }
+    let mut output = vec![];
+    run(Cursor::new(input), &mut output).unwrap();
+    let mut output = output.as_slice();
+    let mut replies = vec![];
+    while let Some(start) = output.windows(4).position(|window| window == b"\r\n\r\n") {
+        let header = std::str::from_utf8(&output[..start]).unwrap();
+        let length: usize = header
+            .strip_prefix("Content-Length: ")
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = &output[start + 4..start + 4 + length];
+        replies.push(serde_json::from_slice(body).unwrap());
+        output = &output[start + 4 + length..];
+    }
+    replies
+}
+
+fn request(id: u64, method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+fn open(text: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {
+            "textDocument": { "uri": URI, "languageId": "electron", "version": 1, "text": text }
+        }
+    })
+}
+
+fn at(id: u64, method: &str, line: u64, character: u64) -> Value {
+    request(
+        id,
+        method,
+        json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character }
+        }),
+    )
+}
+
+fn response(replies: &[Value], id: u64) -> &Value {
+    replies
+        .iter()
+        .find(|reply| reply["id"] == id)
+        .expect("no response")
+}
+
+#[test]
+fn the_server_starts_and_stops_like_the_protocol_says() {
+    let replies = talk(&[
+        request(1, "initialize", json!({ "capabilities": {} })),
+        json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+        request(2, "textDocument/definition", json!({})),
+        request(3, "shutdown", Value::Null),
+        json!({ "jsonrpc": "2.0", "method": "exit" }),
+        // Nothing after exit is read
+        request(4, "shutdown", Value::Null),
+    ]);
+    assert_eq!(replies.len(), 3);
+    let capabilities = &response(&replies, 1)["result"]["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 1);
+    assert_eq!(capabilities["hoverProvider"], true);
+    assert_eq!(response(&replies, 2)["error"]["code"], -32601);
+    assert_eq!(response(&replies, 3)["result"], Value::Null);
+}
+
+#[test]
+fn diagnostics_come_from_the_assembler() {
+    let changed = json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didChange",
+        "params": {
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "IMM R1 1\nOUT %0 R1\n" }]
+        }
+    });
+    let replies = talk(&[open("IMM R1 1\n  LOAD R1 R2\n"), changed]);
+    assert_eq!(replies.len(), 2);
+    assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+    let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+    let errors: Vec<&Value> = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic["severity"] == 1)
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("\"LOAD\" is not a valid instruction"));
+    assert_eq!(
+        errors[0]["range"],
+        json!({ "start": { "line": 1, "character": 2 }, "end": { "line": 1, "character": 6 } })
+    );
+    // Fixing the line clears it
+    assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+}
+
+#[test]
+fn hovers_explain_instructions_and_names() {
+    let source =
+        ".equ START 3\nloop: SADD R1 R2\nOUT %0 R1\nINC R1\nJMP loop\nIMM R2 START\nSHR R3 R1\nNOP";
+    let replies = talk(&[
+        open(source),
+        at(1, "textDocument/hover", 1, 8),
+        at(2, "textDocument/hover", 3, 1),
+        at(3, "textDocument/hover", 4, 6),
+        at(4, "textDocument/hover", 5, 9),
+        at(6, "textDocument/hover", 6, 1),
+        at(7, "textDocument/hover", 7, 1),
+        // Whitespace has nothing to say
+        at(5, "textDocument/hover", 1, 5),
+    ]);
+    let hover = |id| response(&replies, id)["result"]["contents"]["value"].clone();
+    let sadd = hover(1);
+    assert!(sadd.as_str().unwrap().contains("`SADD RA RB`"), "{sadd}");
+    assert!(sadd.as_str().unwrap().contains("written to register A"));
+    assert_eq!(
+        response(&replies, 1)["result"]["range"]["start"],
+        json!({ "line": 1, "character": 6 })
+    );
+    let inc = hover(2);
+    assert!(inc.as_str().unwrap().contains("`SADDC R1 R0`"), "{inc}");
+    assert!(hover(3).as_str().unwrap().contains("label, address 0"));
+    assert!(hover(4).as_str().unwrap().contains("constant, 3"));
+    let shr = hover(6);
+    assert!(shr.as_str().unwrap().contains("`SHR RA RB`"), "{shr}");
+    let nop = hover(7);
+    assert!(nop.as_str().unwrap().contains("`NOP`"), "{nop}");
+    assert_eq!(response(&replies, 5)["result"], Value::Null);
+}
+
+#[test]
+fn completions_and_highlighting_know_the_language() {
+    let replies = talk(&[
+        open("loop: SADD r1 $2 ; add\nJMP loop + 1\n"),
+        at(1, "textDocument/completion", 1, 0),
+        request(
+            2,
+            "textDocument/semanticTokens/full",
+            json!({ "textDocument": { "uri": URI } }),
+        ),
+    ]);
+    let labels: Vec<&str> = response(&replies, 1)["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    for label in ["XADDC", "NOOP", "CMP", ".equ", "R7", "%0", "LOOP"] {
+        assert!(labels.contains(&label), "{label} missing from {labels:?}");
+    }
+    for label in ["LOAD", "PUSH", "BIG", "SSHR"] {
+        assert!(!labels.contains(&label));
+    }
+    let data = &response(&replies, 2)["result"]["data"];
+    // Line, start and length relative to the token before, then the type:
+    // the label, mnemonic, register, number and comment, then the mnemonic,
+    // label name, operator and number
+    assert_eq!(
+        data,
+        &json!([
+            0, 0, 5, 2, 0, //
+            0, 6, 4, 0, 0, //
+            0, 5, 2, 3, 0, //
+            0, 3, 2, 5, 0, //
+            0, 3, 5, 8, 0, //
+            1, 0, 3, 0, 0, //
+            0, 4, 4, 2, 0, //
+            0, 5, 1, 7, 0, //
+            0, 2, 1, 5, 0
+        ])
+    );
+}
+
+#[test]
+fn wrong_content_lengths_are_errors() {
+    let input = "Content-Length: 99999999999\r\n\r\n{}";
+    let error = run(Cursor::new(input), &mut vec![]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+}
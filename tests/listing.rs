@@ -0,0 +1,47 @@
+use electron::parser::*;
+
+#[test]
+fn listing_shows_every_instruction_and_symbol() {
+    let source =
+        ".equ ONE 1\n.alias counter R1\nIMM counter ONE\n\nloop: SADD counter counter\nJMP loop";
+    let program = ProgramLoader::assemble(source).unwrap();
+    let listing = program.listing();
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(
+        lines[1],
+        "  00  10 01 01     3  IMM R1 1          IMM counter ONE"
+    );
+    assert!(lines[2].contains("SADD R1 R1"), "{listing}");
+    assert!(lines[3].starts_with("  02  80 01 00"), "{listing}");
+    let kinds: Vec<(&str, SymbolKind)> = program
+        .symbols
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), symbol.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            ("COUNTER", SymbolKind::Alias),
+            ("LOOP", SymbolKind::Label),
+            ("ONE", SymbolKind::Constant)
+        ]
+    );
+    assert!(listing.contains("R1     line 2"), "{listing}");
+}
+
+#[test]
+fn blank_and_comment_lines_take_no_space() {
+    let source = "; counts up\nIMM R1 1\n\n  ; doubled\nloop:\nSADD R1 R1 ; again\nNOOP\nJMP loop";
+    let program = ProgramLoader::assemble(source).unwrap();
+    let lines: Vec<usize> = program
+        .instructions
+        .iter()
+        .map(|instruction| instruction.source.as_ref().unwrap().line)
+        .collect();
+    assert_eq!(lines, [1, 5, 6, 7]);
+    assert!(matches!(program.instructions[2].operation, Operation::NOOP));
+    assert_eq!(program.instructions[3].a.data(), 1);
+    let source = program.instructions[1].source.as_ref().unwrap();
+    assert_eq!(source.text, "SADD R1 R1 ; again");
+    assert_eq!(source.to_string(), "6");
+}
@@ -0,0 +1,110 @@
+use electron::disassembler::disassemble;
+use electron::emulator::Emulator;
+use electron::machine::Machine;
+use electron::parser::*;
+
+fn assemble_for(machine: &Machine, source: &str) -> Result<Program, Vec<String>> {
+    let options = AssemblyOptions {
+        machine: machine.clone(),
+        ..AssemblyOptions::default()
+    };
+    ProgramLoader::assemble_file_with("program.elt", source, &options).map_err(|diagnostics| {
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    })
+}
+
+#[test]
+fn machines_are_picked_by_name_or_read_from_toml() {
+    assert_eq!(Machine::by_name("electron-v1"), Some(Machine::default()));
+    assert_eq!(Machine::load("Electron-V1"), Ok(Machine::default()));
+    let machine = Machine::from_toml("name = \"Big\"\nrom_lines = 64\nregisters = 16").unwrap();
+    assert_eq!(machine.rom_lines, 64);
+    assert_eq!(machine.registers, 16);
+    assert_eq!(machine.output_ports, Machine::default().output_ports);
+    assert_eq!(machine.rom_bytes(), 192);
+    assert!(Machine::from_toml("rom_size = 64").is_err());
+    let error = Machine::load("no-such-machine.toml").unwrap_err();
+    assert!(error.contains("electron-v1"), "{error}");
+}
+
+#[test]
+fn machines_have_to_fit_in_an_instruction() {
+    for toml in [
+        "rom_lines = 0",
+        "rom_lines = 257",
+        "registers = 129",
+        "ram_size = 300",
+        "word_bits = 9",
+        "word_bits = 0",
+        "window = [720, 0]",
+    ] {
+        assert!(Machine::from_toml(toml).is_err(), "{toml}");
+    }
+    let machine = Machine::from_toml("rom_lines = 256\nregisters = 128\nword_bits = 1").unwrap();
+    assert_eq!(machine.max_word(), 1);
+    assert_eq!(Machine::default().max_word(), 255);
+}
+
+#[test]
+fn programs_are_checked_against_their_machine() {
+    let source = "IMM R12 1\nJMP 40\nOUT %9 R12";
+    let errors = assemble_for(&Machine::default(), source).unwrap_err();
+    assert_eq!(errors.len(), 3, "{errors:?}");
+    let machine = Machine::from_toml("rom_lines = 64\nregisters = 16\noutput_ports = 10").unwrap();
+    let program = assemble_for(&machine, source).unwrap();
+    assert_eq!(program.machine, machine);
+    let rom = program.to_rom().unwrap();
+    assert_eq!(rom.len(), 64 * Instruction::SIZE);
+    // Jump targets past line 32 still get a label
+    let disassembled = disassemble(&rom).unwrap();
+    let reassembled = assemble_for(&machine, &disassembled).unwrap();
+    assert_eq!(reassembled.to_rom().unwrap(), rom);
+
+    let nibble = Machine::from_toml("word_bits = 4").unwrap();
+    let errors = assemble_for(&nibble, "IMM R1 16").unwrap_err();
+    assert!(
+        errors[0].contains("does not fit in 4 bits (-8 to 15)"),
+        "{errors:?}"
+    );
+    let program = assemble_for(&nibble, "IMM R1 -8").unwrap();
+    assert_eq!(program.instructions[0].to_string(), "IMM R1 8");
+}
+
+#[test]
+fn jump_targets_are_bounded_by_rom_not_words() {
+    let nibble = Machine::from_toml("word_bits = 4").unwrap();
+    let mut source = "JMP 20\nNOOP\n".to_string();
+    source += &"NOOP\n".repeat(18);
+    source += "end: IMM R1 9\nOUT %0 R1\nJMP end\nNOOP";
+    let program = assemble_for(&nibble, &source).unwrap();
+    assert_eq!(program.instructions[0].to_string(), "JMP 20");
+    let mut emulator = Emulator::new(program);
+    for _ in 0..16 {
+        emulator.clock();
+    }
+    assert_eq!(emulator.ports.out[0], 9);
+    let errors = assemble_for(&nibble, "JMP 32").unwrap_err();
+    assert_eq!(errors, ["Jump target 32 is past the end of ROM (32 lines)"]);
+    let errors = assemble_for(&nibble, "JMP -1").unwrap_err();
+    assert_eq!(errors, ["Jump target \"-1\" is before the start of ROM"]);
+    // Aliases are read as words, then checked the same way
+    let errors = assemble_for(&Machine::default(), ".alias far 40\nJMP far").unwrap_err();
+    assert_eq!(errors, ["Jump target 40 is past the end of ROM (32 lines)"]);
+}
+
+#[test]
+fn the_emulator_is_as_big_as_its_machine() {
+    let machine = Machine::from_toml("rom_lines = 6\nword_bits = 4\noutput_ports = 2").unwrap();
+    let source = "IMM R1 9\nIMM R2 9\nNOOP\nSADD R1 R2\nNOOP\nOUT %1 R1";
+    let mut emulator = Emulator::new(assemble_for(&machine, source).unwrap());
+    assert_eq!(emulator.ports.out.len(), 2);
+    for _ in 0..16 {
+        emulator.clock();
+        assert!((emulator.program_counter as usize) < machine.rom_lines);
+    }
+    // 9 + 9 doesn't fit in 4 bits, so it wraps around to 2
+    assert_eq!(emulator.ports.out[1], 2);
+}
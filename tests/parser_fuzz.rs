@@ -0,0 +1,101 @@
+use electron::formatter::format;
+use electron::parser::ProgramLoader;
+use electron::syntax::SyntaxTree;
+
+mod common;
+use common::Rng;
+
+#[rustfmt::skip]
+const WORDS: &[&str] = &[
+    "IMM", "MOV", "ADD", "ADDC", "SADD", "UADDC", "XADD", "AADD", "SHR", "NOT", "NOOP", "NOP",
+    "OUT", "JMP", "BIE", "SJMP", "SUB", "INC", "DEC", "CLR", "CMP", "NEG", "LDI", "R", "R0", "R7",
+    "R8", "RX", "RB101", "RB102", "%", "%3", "%9", "#", "#31", "#32", "B", "B101", "B1_0", "B2",
+    "0", "255", "256", "300", "99999999999", "_", "loop", "loop:", ":", "::", ";", "; comment", "é",
+    "Ré", "\t", " ", "", "-1", "0x1F", "0xG", "-129", "$ff", "0o17", "0b1_0", "'a'", "';'", "'\\''",
+    "'ab'", "'", "(", ")", "(1<<7)|3", "+", "-", "<<", ">>", "~", "/0", "loop+2", "R(", "%(1",
+    ".equ", ".MACRO", ".endm", ".include", "\"x.elt\"", "M", "m", ".alias", ".EQU X", ".alias R1",
+    ".",
+];
+
+const CHARS: &[char] = &[
+    'R', 'B', 'X', 'S', 'U', '%', '#', '0', '1', '9', '_', ':', ';', ' ', '\t', 'é', '\u{0}', '\n',
+    '\'', '\\', '$', '-', 'o', '(', ')', '+', '*', '/', '<', '>', '&', '|', '^', '~',
+];
+
+#[test]
+fn random_words_never_panic() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..20_000 {
+        let mut source = String::new();
+        for _ in 0..rng.below(4) {
+            for _ in 0..rng.below(5) {
+                source += *rng.pick(WORDS);
+                source += " ";
+            }
+            source += "\n";
+        }
+        let _ = ProgramLoader::assemble(&source);
+    }
+}
+
+#[test]
+fn random_characters_never_panic() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..20_000 {
+        let source: String = (0..rng.below(24)).map(|_| *rng.pick(CHARS)).collect();
+        let _ = ProgramLoader::assemble(&source);
+    }
+}
+
+#[test]
+fn deeply_nested_expressions_are_errors() {
+    let brackets = "(".repeat(10_000);
+    let tildes = "~".repeat(50_000);
+    for oprand in [
+        format!("{brackets}1"),
+        format!("{tildes}1"),
+        format!("-{tildes}1"),
+    ] {
+        let diagnostics = ProgramLoader::assemble(&format!("IMM R1 {oprand}")).unwrap_err();
+        assert_eq!(diagnostics[0].message, "Expression is nested too deeply");
+    }
+    // Nesting that people write still works
+    let source = format!("IMM R1 {}1{}", "(".repeat(100), ")".repeat(100));
+    assert!(ProgramLoader::assemble(&source).is_ok());
+}
+
+#[test]
+fn random_sources_format_the_same_every_time() {
+    let mut rng = Rng(0x6a09_e667_f3bc_c908);
+    for _ in 0..20_000 {
+        let mut source = String::new();
+        for _ in 0..rng.below(4) {
+            for _ in 0..rng.below(5) {
+                source += *rng.pick(WORDS);
+                source.push(*rng.pick(CHARS));
+            }
+            source += "\n";
+        }
+        assert_eq!(SyntaxTree::parse(&source).to_string(), source);
+        let formatted = format(&source);
+        assert_eq!(format(&formatted), formatted, "{source:?}");
+    }
+}
+
+#[test]
+fn malformed_oprands_are_errors() {
+    for source in [
+        "IMM R1 300",
+        "IMM RX 1",
+        "IMM R1 B102",
+        "MOV R1",
+        "MOV R1 R2 R3",
+        "MOV R8 R1",
+        "OUT %8 R1",
+        "IMM R1 #",
+        "AADD R1 R2",
+    ] {
+        let diagnostics = ProgramLoader::assemble(source).unwrap_err();
+        assert!(diagnostics.iter().any(|d| d.is_error()), "{source}");
+    }
+}
@@ -0,0 +1,285 @@
+use electron::diagnostic::Diagnostic;
+use electron::parser::*;
+use std::path::Path;
+
+mod common;
+use common::*;
+
+// Writes the files of a program, returning the path of the first one
+fn write_files(directory: &Path, files: &[(&str, &str)]) -> String {
+    for (name, source) in files {
+        let path = directory.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+    directory.join(files[0].0).to_string_lossy().to_string()
+}
+
+// The errors, without the warnings, from loading a program that fails to
+// assemble
+fn load_errors(file_name: &str) -> Vec<Diagnostic> {
+    let diagnostics = ProgramLoader::load_program(file_name).unwrap_err();
+    diagnostics
+        .into_iter()
+        .filter(Diagnostic::is_error)
+        .collect()
+}
+
+#[test]
+fn files_are_included_relative_to_the_file_including_them() {
+    let directory = temp_dir("relative");
+    let main = write_files(
+        &directory,
+        &[
+            ("main.elt", ".include \"lib/display.elt\"\nOUT %0 R1"),
+            ("lib/display.elt", ".include \"digits.elt\"\nSADD R1 R1"),
+            ("lib/digits.elt", "IMM R1 21"),
+        ],
+    );
+    let program = ProgramLoader::load_program(&main).unwrap();
+    assert_eq!(
+        code(&program.instructions),
+        ["IMM R1 21", "SADD R1 R1", "OUT %0 R1"]
+    );
+    assert_eq!(run(program)[0], 42);
+}
+
+#[test]
+fn missing_files_are_errors() {
+    let directory = temp_dir("missing");
+    let main = write_files(
+        &directory,
+        &[("main.elt", "IMM R1 1\n.include \"gone.elt\"")],
+    );
+    let errors = load_errors(&main);
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    let gone = directory.join("gone.elt").to_string_lossy().to_string();
+    assert!(
+        errors[0]
+            .message
+            .starts_with(&format!("Could not open {gone}: ")),
+        "{errors:?}"
+    );
+    assert_eq!((errors[0].line, errors[0].column), (1, 9));
+    assert_eq!(errors[0].length, 10);
+}
+
+#[test]
+fn files_including_each_other_are_errors() {
+    let directory = temp_dir("cycle");
+    let main = write_files(
+        &directory,
+        &[
+            ("a.elt", ".include \"b.elt\"\nIMM R1 1"),
+            ("b.elt", "IMM R2 2\n.include \"a.elt\""),
+        ],
+    );
+    let errors = load_errors(&main);
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    let error = &errors[0];
+    let a = directory.join("a.elt").to_string_lossy().to_string();
+    let b = directory.join("b.elt").to_string_lossy().to_string();
+    assert_eq!(
+        error.message,
+        format!("Including {a} would never end, it is already being included")
+    );
+    assert_eq!(error.file, Some(b));
+    assert_eq!(error.line, 1);
+    assert_eq!(error.notes.len(), 1);
+    assert_eq!(error.notes[0].file, None);
+    assert_eq!(error.notes[0].line, 0);
+}
+
+#[test]
+fn errors_in_included_files_show_the_include_chain() {
+    let directory = temp_dir("chain");
+    let main = write_files(
+        &directory,
+        &[
+            ("main.elt", "IMM R1 1\n.include \"middle.elt\""),
+            ("middle.elt", "; the display\n.include \"inner.elt\""),
+            ("inner.elt", "IMM R1 1\nADDD R1 R1"),
+        ],
+    );
+    let errors = load_errors(&main);
+    let error = &errors[0];
+    let middle = directory.join("middle.elt").to_string_lossy().to_string();
+    let inner = directory.join("inner.elt").to_string_lossy().to_string();
+    assert_eq!(error.file.as_ref(), Some(&inner));
+    let notes: Vec<(Option<&str>, usize, &str)> = error
+        .notes
+        .iter()
+        .map(|note| (note.file.as_deref(), note.line, note.message.as_str()))
+        .collect();
+    assert_eq!(
+        notes,
+        [
+            (Some(middle.as_str()), 1, "in the file included from here"),
+            (None, 1, "in the file included from here"),
+        ]
+    );
+    // Rendering doesn't need the included files any more
+    std::fs::remove_dir_all(&directory).unwrap();
+    let rendered = error.render(&main);
+    assert!(
+        rendered.contains(&format!("--> {inner}:2:1\n  |\n2 | ADDD R1 R1\n  | ^^^^\n")),
+        "{rendered}"
+    );
+    assert!(
+        rendered.contains(&format!(
+            "--> {middle}:2:10\n  |\n2 | .include \"inner.elt\"\n"
+        )),
+        "{rendered}"
+    );
+    assert!(
+        rendered.contains(&format!(
+            "--> {main}:2:10\n  |\n2 | .include \"middle.elt\"\n"
+        )),
+        "{rendered}"
+    );
+}
+
+#[test]
+fn macros_expand_with_their_arguments() {
+    let source =
+        ".macro double reg\nSADD reg reg\n.endm\nIMM R1 3\ndouble R1\nDouble R1\nOUT %0 R1";
+    let program = assemble(source);
+    assert_eq!(
+        code(&program.instructions),
+        ["IMM R1 3", "SADD R1 R1", "SADD R1 R1", "OUT %0 R1"]
+    );
+    assert_eq!(run(program)[0], 12);
+}
+
+#[test]
+fn macro_labels_are_renamed_for_every_expansion() {
+    let program = assemble(".macro wait\nagain: NOOP\nJMP again\nNOOP\n.endm\nwait\nwait");
+    assert_eq!(
+        code(&program.instructions),
+        ["NOOP", "JMP 0", "NOOP", "NOOP", "JMP 3", "NOOP"]
+    );
+    let labels: Vec<(&str, &str)> = program
+        .symbols
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), symbol.value.as_str()))
+        .collect();
+    assert_eq!(labels, [("AGAIN__1", "0"), ("AGAIN__2", "3")]);
+}
+
+#[test]
+fn macros_using_themselves_are_errors() {
+    let errors = assembly_errors(".macro loop\nloop\n.endm\nloop");
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(
+        errors[0].message,
+        "Macro LOOP uses itself, so it would never end"
+    );
+    assert_eq!(errors[0].line, 1);
+    assert_eq!(errors[0].notes[0].line, 3);
+
+    let errors = assembly_errors(".macro a\nb\n.endm\n.macro b\na\n.endm\na");
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(
+        errors[0].message,
+        "Macro A uses itself, so it would never end"
+    );
+    let notes: Vec<(usize, &str)> = errors[0]
+        .notes
+        .iter()
+        .map(|note| (note.line, note.message.as_str()))
+        .collect();
+    assert_eq!(
+        notes,
+        [
+            (1, "in this expansion of macro B"),
+            (6, "in this expansion of macro A")
+        ]
+    );
+}
+
+#[test]
+fn macros_need_every_argument() {
+    let errors = assembly_errors(".macro set reg value\nIMM reg value\n.endm\nset R1\nset R1 2 3");
+    let found: Vec<(usize, &str)> = errors
+        .iter()
+        .map(|error| (error.line, error.message.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (3, "Macro SET takes 2 arguments but was given 1"),
+            (4, "Macro SET takes 2 arguments but was given 3")
+        ]
+    );
+}
+
+#[test]
+fn errors_in_macros_point_at_the_definition_and_the_call() {
+    let source = ".macro show reg\nOUT %9 reg\n.endm\nIMM R1 1\n  show R1";
+    let errors = assembly_errors(source);
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(
+        errors[0].render("program.elt"),
+        "error: There is no port 9, the highest is 7
+ --> program.elt:2:5
+  |
+2 | OUT %9 reg
+  |     ^^
+note: in this expansion of macro SHOW
+ --> program.elt:5:3
+  |
+5 |   show R1
+  |   ^^^^
+"
+    );
+}
+
+#[test]
+fn unfinished_macros_are_errors() {
+    let messages = |source: &str| -> Vec<String> {
+        assembly_errors(source)
+            .into_iter()
+            .map(|error| error.message)
+            .collect()
+    };
+    assert_eq!(messages("NOOP\n.endm"), [".ENDM without a .MACRO to end"]);
+    assert_eq!(
+        messages(".macro forever\nNOOP"),
+        ["Macro FOREVER is missing its .ENDM"]
+    );
+    assert_eq!(
+        messages(".macro outer\n.macro inner\n.endm\n.endm"),
+        [
+            "Macros can't be defined inside other macros",
+            ".ENDM without a .MACRO to end"
+        ]
+    );
+}
+
+#[test]
+fn instructions_know_where_they_were_written() {
+    let directory = temp_dir("sources");
+    let main = write_files(
+        &directory,
+        &[
+            ("main.elt", "IMM R1 1\n.include \"sub.elt\""),
+            ("sub.elt", "; subtracts\nSUB R1 R2"),
+        ],
+    );
+    let program = ProgramLoader::load_program(&main).unwrap();
+    let sources: Vec<&SourceLocation> = program
+        .instructions
+        .iter()
+        .map(|instruction| instruction.source.as_ref().unwrap())
+        .collect();
+    assert_eq!(sources[0].file, None);
+    assert_eq!(sources[0].to_string(), "1");
+    assert!(sources.len() > 2, "{:?}", code(&program.instructions));
+    let sub = directory.join("sub.elt").to_string_lossy().to_string();
+    for (part, source) in sources[1..].iter().enumerate() {
+        assert_eq!(source.file.as_ref(), Some(&sub));
+        assert_eq!((source.line, source.part), (1, part));
+        assert_eq!(source.text, "SUB R1 R2");
+        assert_eq!(source.to_string(), format!("{sub}:2"));
+    }
+}
@@ -0,0 +1,70 @@
+use electron::diagnostic::{Diagnostic, Severity};
+use electron::emulator::Emulator;
+use electron::parser::*;
+
+mod common;
+use common::*;
+
+fn severities(diagnostics: &[Diagnostic]) -> Vec<(Severity, &str)> {
+    diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.message.as_str()))
+        .collect()
+}
+
+#[test]
+fn warnings_come_with_the_program_or_the_errors() {
+    let program = ProgramLoader::assemble("IMM R1 1\nIMM R2 2\nOUT %0 R2").unwrap();
+    assert_eq!(
+        severities(&program.warnings),
+        [(
+            Severity::Warning,
+            "\"IMM R1 1\" writes R1, but nothing reads that value"
+        )]
+    );
+    let diagnostics =
+        ProgramLoader::assemble("IMM R1 1\nIMM R2 2\nOUT %0 R2\nADDD R2").unwrap_err();
+    assert_eq!(
+        severities(&diagnostics),
+        [
+            (
+                Severity::Warning,
+                "\"IMM R1 1\" writes R1, but nothing reads that value"
+            ),
+            (Severity::Error, "\"ADDD\" is not a valid instruction"),
+        ]
+    );
+    let from_reader = ProgramLoader::from_reader("ADDD R2".as_bytes()).unwrap_err();
+    assert_eq!(severities(&from_reader), severities(&diagnostics)[1..]);
+}
+
+#[test]
+fn missing_programs_are_errors() {
+    let errors = ProgramLoader::load_program("no/such/program.elt").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(
+        errors[0]
+            .message
+            .starts_with("Could not open no/such/program.elt: "),
+        "{errors:?}"
+    );
+}
+
+#[test]
+fn unpadded_programs_run_noops_until_the_end_of_rom() {
+    let program = assemble("IMM R2 1\nNOOP\nSADD R1 R2\nNOOP\nOUT %0 R1");
+    assert_eq!(program.instructions.len(), 5);
+    assert_eq!(program.to_rom().unwrap().len(), 32 * Instruction::SIZE);
+    let mut emulator = Emulator::new(program);
+    for _ in 0..32 {
+        emulator.clock();
+        assert!(emulator.program_counter < 32);
+    }
+    // The program ran once, then the counter wrapped around to run it again
+    assert_eq!(emulator.program_counter, 0);
+    assert_eq!(emulator.ports.out[0], 1);
+    for _ in 0..32 {
+        emulator.clock();
+    }
+    assert_eq!(emulator.ports.out[0], 2);
+}
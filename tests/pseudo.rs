@@ -0,0 +1,61 @@
+use electron::parser::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn pseudo_instructions_compute_their_results() {
+    let program = assemble(
+        r"
+    IMM R1 10
+    IMM R2 3
+    SUB R1 R2
+    OUT %0 R1
+    INC R1
+    OUT %1 R1
+    DEC R2
+    OUT %2 R2
+    NEG R2
+    OUT %3 R2
+    CLR R1
+    OUT %4 R1
+    LDI R3 7
+    CMP R3 R3
+    BIE done
+    NOOP
+    OUT %5 R3
+    done:
+    IMM R4 1
+    OUT %6 R4
+    JMP done
+    ",
+    );
+    assert_eq!(run(program), [7, 8, 2, 254, 0, 0, 1, 0]);
+}
+
+#[test]
+fn labels_count_expanded_instructions() {
+    let program = ProgramLoader::assemble("SUB R1 R2\nloop: INC R1\nJMP loop").unwrap();
+    assert_eq!(program.instructions.len(), 4);
+    assert_eq!(program.instructions[3].a.data(), 2);
+    let parts: Vec<usize> = program
+        .instructions
+        .iter()
+        .map(|instruction| instruction.source.as_ref().unwrap().part)
+        .collect();
+    assert_eq!(parts, [0, 1, 0, 0]);
+}
+
+#[test]
+fn expanded_size_counts_towards_rom() {
+    let program = ProgramLoader::assemble(&"SUB R1 R2\n".repeat(17)).unwrap();
+    assert_eq!(program.instructions.len(), 34);
+    assert!(program
+        .warnings
+        .iter()
+        .any(|warning| warning.message.contains("34/32")));
+    let errors = ProgramLoader::assemble("INC R1 R2").unwrap_err();
+    assert!(errors[0].message.contains("INC only takes 1 oprand"));
+    let program = ProgramLoader::assemble("INC 3").unwrap();
+    assert!(program.warnings[0].message.contains("INC takes a Register"));
+}
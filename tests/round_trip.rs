@@ -0,0 +1,81 @@
+use electron::disassembler::disassemble;
+use electron::parser::ProgramLoader;
+
+mod common;
+use common::Rng;
+
+fn rom(source: &str) -> Vec<u8> {
+    let program = ProgramLoader::assemble(source)
+        .unwrap_or_else(|errors| panic!("{source}\nfailed to assemble: {errors:?}"));
+    program.to_rom().unwrap()
+}
+
+// Assembles, disassembles and assembles again, checking the ROM is unchanged
+fn assert_round_trips(source: &str) {
+    let first = rom(source);
+    let disassembled = disassemble(&first).unwrap();
+    let second = rom(&disassembled);
+    assert_eq!(first, second, "{source}\ndisassembled to\n{disassembled}");
+}
+
+#[test]
+fn example_programs_round_trip() {
+    for source in [
+        include_str!("../fibbonaci.elt"),
+        include_str!("../heart.elt"),
+        include_str!("../example.elt"),
+    ] {
+        assert_round_trips(source);
+    }
+}
+
+#[test]
+fn random_programs_round_trip() {
+    let mut rng = Rng(0x853c_49e6_748f_ea9b);
+    for _ in 0..2_000 {
+        let mut source = String::new();
+        for _ in 0..rng.below(33) {
+            let register = |rng: &mut Rng| format!("R{}", rng.below(8));
+            let line = match rng.below(10) {
+                0 => "NOOP".to_string(),
+                1 => format!("IMM {} {}", register(&mut rng), rng.below(256)),
+                2 => format!("MOV {} {}", register(&mut rng), register(&mut rng)),
+                3 | 4 => {
+                    let operation = ["ADD", "ADDC"][rng.below(2) as usize];
+                    let prefix = ["", "S", "U"][rng.below(3) as usize];
+                    let (a, b) = (register(&mut rng), register(&mut rng));
+                    format!("{prefix}{operation} {a} {b}")
+                }
+                5 => format!("XADD {}", register(&mut rng)),
+                6 => {
+                    let operation = ["SHR", "NOT"][rng.below(2) as usize];
+                    format!("{operation} {} {}", register(&mut rng), register(&mut rng))
+                }
+                7 => format!("OUT %{} {}", rng.below(8), register(&mut rng)),
+                8 => format!("JMP {}", rng.below(32)),
+                _ => format!("BIE {}", rng.below(32)),
+            };
+            source += &line;
+            source += "\n";
+        }
+        assert_round_trips(&source);
+    }
+}
+
+#[test]
+fn jump_targets_get_labels() {
+    let disassembled = disassemble(&rom("IMM R1 1\nloop: SADD R1 R1\nJMP loop")).unwrap();
+    assert!(disassembled.contains("L1:\n"), "{disassembled}");
+    assert!(disassembled.contains("JMP L1 "), "{disassembled}");
+}
+
+#[test]
+fn malformed_roms_are_errors() {
+    assert!(disassemble(&[0x10, 0x01]).is_err());
+    assert!(disassemble(&[0xF0, 0x00, 0x00]).is_err());
+    assert!(disassemble(&[0x24, 0x01, 0x02]).is_err());
+    // No machine has more than 256 lines of ROM for jumps to reach
+    assert!(disassemble(&[0; 256 * 3]).is_ok());
+    let error = disassemble(&[0; 257 * 3]).unwrap_err();
+    assert!(error.contains("257 lines"), "{error}");
+}
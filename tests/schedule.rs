@@ -0,0 +1,76 @@
+use electron::hazards::*;
+use electron::parser::*;
+use electron::schedule::*;
+
+mod common;
+use common::*;
+
+fn scheduled(source: &str) -> Program {
+    let options = AssemblyOptions {
+        schedule: true,
+        ..AssemblyOptions::default()
+    };
+    assemble_with(source, options)
+}
+
+#[test]
+fn scheduled_programs_run_as_written() {
+    let source = "IMM R1 5\nIMM R2 3\nADD R1 R2\nIMM R3 1\nUADD R5 R0\nOUT %0 R5\nOUT %1 R3";
+    assert_eq!(run(assemble(source))[0], 0);
+    let scheduled = scheduled(source);
+    assert!(scheduled.warnings.is_empty(), "{:?}", scheduled.warnings);
+    assert_eq!(run(scheduled)[..2], [8, 1]);
+}
+
+#[test]
+fn jumps_follow_moved_instructions() {
+    let source = "IMM R2 1\nloop: SADD R1 R2\nIMM R3 7\nUADD R4 R0\nOUT %0 R4\nJMP loop";
+    let program = scheduled(source);
+    assert_eq!(
+        code(&program.instructions),
+        [
+            "IMM R2 1",
+            "IMM R3 7",
+            "SADD R1 R2",
+            "UADD R4 R0",
+            "OUT %0 R4",
+            "JMP 1"
+        ]
+    );
+    assert_eq!(program.symbols[0].value, "1");
+    assert!(program.instructions[2].source.is_some());
+}
+
+#[test]
+fn dependent_instructions_stay_in_order() {
+    // MOV reads the register the ADD writes, so it can't go before it
+    let program = scheduled("SADD R1 R2\nMOV R3 R1\nUADD R4 R0");
+    assert_eq!(program.instructions[0].to_string(), "SADD R1 R2");
+    let hazards = program
+        .warnings
+        .iter()
+        .filter(|warning| warning.message.contains("uses the accumalator"));
+    assert_eq!(hazards.count(), 1);
+}
+
+#[test]
+fn slower_pipelines_get_noops() {
+    let pipeline = Pipeline {
+        later_stages_first: false,
+        ..Pipeline::EMULATOR
+    };
+    let program = assemble("IMM R1 5\nloop: SADD R1 R1\nJMP loop");
+    let scheduled = schedule(&program.instructions, &pipeline, &program.machine).unwrap();
+    assert_eq!(
+        code(&scheduled.instructions),
+        ["IMM R1 5", "NOOP", "SADD R1 R1", "JMP 1"]
+    );
+    assert_eq!(scheduled.from, [Some(0), None, Some(1), Some(2)]);
+    assert_eq!(scheduled.relocate(1), 1);
+    assert_eq!(scheduled.relocate(5), 6);
+    let program = assemble(&"IMM R1 1\nSADD R1 R1\n".repeat(16));
+    let error = schedule(&program.instructions, &pipeline, &program.machine).unwrap_err();
+    assert!(error.message.contains("48 lines"), "{error:?}");
+    // The first SADD's NOOP is the first line that doesn't fit
+    assert_eq!(error.address, 1);
+}
@@ -0,0 +1,330 @@
+use electron::machine::Machine;
+use electron::nbt::Tag;
+use electron::parser::ProgramLoader;
+use electron::schematic::*;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+fn read_schematic(source: &str, layout: &RomLayout) -> Tag {
+    let program = ProgramLoader::assemble(source).unwrap();
+    let mut file = vec![];
+    write_schematic(&program, layout, &mut file).unwrap();
+    let (name, schematic) = Tag::read(&mut GzDecoder::new(file.as_slice())).unwrap();
+    assert_eq!(name, "Schematic");
+    schematic
+}
+
+fn read_varints(bytes: &[i8]) -> Vec<usize> {
+    let mut values = vec![];
+    let (mut value, mut shift) = (0, 0);
+    for byte in bytes {
+        value |= (*byte as u8 as usize & 0x7F) << shift;
+        shift += 7;
+        if *byte >= 0 {
+            values.push(value);
+            (value, shift) = (0, 0);
+        }
+    }
+    values
+}
+
+fn short(schematic: &Tag, name: &str) -> usize {
+    match schematic.get(name) {
+        Some(Tag::Short(value)) => *value as usize,
+        tag => panic!("{name} is {tag:?}"),
+    }
+}
+
+#[test]
+fn every_tag_reads_back() {
+    let tag = Tag::Compound(vec![
+        ("byte".to_string(), Tag::Byte(-3)),
+        ("short".to_string(), Tag::Short(-300)),
+        ("int".to_string(), Tag::Int(70_000)),
+        ("long".to_string(), Tag::Long(-1 << 40)),
+        ("float".to_string(), Tag::Float(1.5)),
+        ("double".to_string(), Tag::Double(-2.25)),
+        ("bytes".to_string(), Tag::ByteArray(vec![1, -1, 127])),
+        (
+            "string".to_string(),
+            Tag::String("minecraft:stone".to_string()),
+        ),
+        (
+            "list".to_string(),
+            Tag::List(vec![Tag::Int(1), Tag::Int(2)]),
+        ),
+        ("empty".to_string(), Tag::List(vec![])),
+        ("ints".to_string(), Tag::IntArray(vec![-1, 0, 1])),
+        ("longs".to_string(), Tag::LongArray(vec![i64::MAX])),
+        ("nested".to_string(), Tag::Compound(vec![])),
+    ]);
+    let mut bytes = vec![];
+    tag.write("root", &mut bytes).unwrap();
+    let (name, read) = Tag::read(&mut bytes.as_slice()).unwrap();
+    assert_eq!(name, "root");
+    assert_eq!(read, tag);
+}
+
+#[test]
+fn mixed_lists_are_rejected() {
+    let tag = Tag::List(vec![Tag::Int(1), Tag::Byte(1)]);
+    assert!(tag.write("list", &mut vec![]).is_err());
+}
+
+#[test]
+fn deeply_nested_tags_are_rejected() {
+    // Compounds inside `depth` compounds, inside a root compound
+    let nested = |depth: usize| {
+        let mut tag = Tag::Compound(vec![]);
+        for _ in 0..depth {
+            tag = Tag::Compound(vec![("inner".to_string(), tag)]);
+        }
+        let mut bytes = vec![];
+        tag.write("root", &mut bytes).unwrap();
+        bytes
+    };
+    assert!(Tag::read(&mut nested(511).as_slice()).is_ok());
+    let error = Tag::read(&mut nested(512).as_slice()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    // Far too deep to read recursively, as a list of lists
+    let mut bytes = vec![9, 0, 0];
+    for _ in 0..1_000_000 {
+        bytes.extend([9, 0, 0, 0, 1]);
+    }
+    let error = Tag::read(&mut bytes.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn schematic_holds_the_rom() {
+    let source = "IMM R1 108\nSADD R1 R1\nJMP 1";
+    let layout = RomLayout::default();
+    let schematic = read_schematic(source, &layout);
+    assert_eq!(schematic.get("Version"), Some(&Tag::Int(2)));
+    let (width, height, length) = (
+        short(&schematic, "Width"),
+        short(&schematic, "Height"),
+        short(&schematic, "Length"),
+    );
+    assert_eq!((width, height, length), (15, 5, 63));
+    let Some(Tag::Compound(palette)) = schematic.get("Palette") else {
+        panic!("missing palette");
+    };
+    let index_of = |block: &str| match palette.iter().find(|(name, _)| name == block) {
+        Some((_, Tag::Int(index))) => *index as usize,
+        _ => panic!("{block} is not in the palette"),
+    };
+    let Some(Tag::ByteArray(block_data)) = schematic.get("BlockData") else {
+        panic!("missing block data");
+    };
+    let blocks = read_varints(block_data);
+    assert_eq!(blocks.len(), width * height * length);
+    let rom = ProgramLoader::assemble(source).unwrap().to_rom().unwrap();
+    for line in 0..32 {
+        for byte in 0..3 {
+            for bit in 0..8 {
+                let [x, y, z] = layout.position(line, byte, bit).map(|axis| axis as usize);
+                let set = rom[line * 3 + byte] >> bit & 1 == 1;
+                let expected = index_of(if set { &layout.one } else { &layout.zero });
+                assert_eq!(blocks[x + z * width + y * width * length], expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn layouts_can_face_any_way() {
+    let layout = RomLayout::from_toml(
+        "origin = [5, 1, 0]\nbit = [0, 0, -1]\nbyte = [0, 0, -9]\nline = [-1, 0, 0]\n\
+         one = \"minecraft:stone\"",
+    )
+    .unwrap();
+    let schematic = read_schematic("IMM R1 1", &layout);
+    assert_eq!(short(&schematic, "Width"), 32);
+    assert_eq!(short(&schematic, "Height"), 1);
+    assert_eq!(short(&schematic, "Length"), 26);
+    assert_eq!(
+        schematic.get("Offset"),
+        Some(&Tag::IntArray(vec![-26, 1, -25]))
+    );
+}
+
+#[test]
+fn overlapping_layouts_are_errors() {
+    let layout = RomLayout::from_toml("byte = [1, 0, 0]").unwrap();
+    let program = ProgramLoader::assemble("NOOP").unwrap();
+    assert!(write_schematic(&program, &layout, &mut vec![]).is_err());
+    assert!(RomLayout::from_toml("bits = [1, 0, 0]").is_err());
+}
+
+fn gzip(name: &str, tag: &Tag) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    tag.write(name, &mut encoder).unwrap();
+    encoder.finish().unwrap()
+}
+
+// Packs palette indexes the way Litematica does, splitting them across longs
+fn pack(indexes: &[usize], bits: usize) -> Vec<i64> {
+    let mut longs = vec![0u64; (indexes.len() * bits).div_ceil(64)];
+    for (i, index) in indexes.iter().enumerate() {
+        let (long, offset) = (i * bits / 64, i * bits % 64);
+        longs[long] |= (*index as u64) << offset;
+        if offset + bits > 64 {
+            longs[long + 1] |= (*index as u64) >> (64 - offset);
+        }
+    }
+    longs.into_iter().map(|long| long as i64).collect()
+}
+
+#[test]
+fn exported_schematics_import_back() {
+    let source = "IMM R1 108\nloop: SADD R1 R1\nOUT %3 R1\nBIE loop\nJMP 0";
+    let program = ProgramLoader::assemble(source).unwrap();
+    for layout in [
+        RomLayout::default(),
+        RomLayout::from_toml(
+            "origin = [40, 7, 8]\nbit = [0, -1, 0]\nbyte = [0, 0, 1]\nline = [-1, 0, 0]",
+        )
+        .unwrap(),
+        // Positions below zero end up at the schematic's lowest corner too
+        RomLayout::from_toml("origin = [0, 0, 0]\nbit = [-1, 0, 0]\nline = [0, 0, -3]").unwrap(),
+    ] {
+        let mut file = vec![];
+        write_schematic(&program, &layout, &mut file).unwrap();
+        assert_eq!(
+            read_rom(file.as_slice(), &layout, &Machine::default()).unwrap(),
+            program.to_rom().unwrap()
+        );
+    }
+}
+
+fn xyz(x: i32, y: i32, z: i32) -> Tag {
+    Tag::Compound(vec![
+        ("x".to_string(), Tag::Int(x)),
+        ("y".to_string(), Tag::Int(y)),
+        ("z".to_string(), Tag::Int(z)),
+    ])
+}
+
+// A gzipped Litematica schematic of one region
+fn litematic(position: Tag, size: Tag, palette: &[&str], states: Vec<i64>) -> Vec<u8> {
+    let state =
+        |name: &&str| Tag::Compound(vec![("Name".to_string(), Tag::String(name.to_string()))]);
+    let region = Tag::Compound(vec![
+        ("Position".to_string(), position),
+        ("Size".to_string(), size),
+        (
+            "BlockStatePalette".to_string(),
+            Tag::List(palette.iter().map(state).collect()),
+        ),
+        ("BlockStates".to_string(), Tag::LongArray(states)),
+    ]);
+    let litematic = Tag::Compound(vec![
+        ("Version".to_string(), Tag::Int(6)),
+        (
+            "Regions".to_string(),
+            Tag::Compound(vec![("rom".to_string(), region)]),
+        ),
+    ]);
+    gzip("", &litematic)
+}
+
+#[test]
+fn litematics_import() {
+    // A single line of ROM, 24 blocks along x, with the region growing
+    // backwards from its position
+    let rom = [0x10, 0x01, 0x6C];
+    let layout = RomLayout::from_toml("bit = [1, 0, 0]\nbyte = [8, 0, 0]").unwrap();
+    let palette = [
+        "minecraft:air",
+        "minecraft:redstone_torch",
+        "minecraft:stone",
+    ];
+    let indexes: Vec<usize> = (0..24)
+        .map(|i| usize::from(rom[i / 8] >> (7 - i % 8) & 1 == 1))
+        .collect();
+    let file = litematic(xyz(23, 0, 0), xyz(-24, 1, 1), &palette, pack(&indexes, 2));
+    let read = read_rom(file.as_slice(), &layout, &Machine::default()).unwrap();
+    assert_eq!(read[..3], rom);
+    assert!(read[3..].iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn litematics_out_of_range_are_errors() {
+    let layout = RomLayout::default();
+    let air = ["minecraft:air"];
+    let file = litematic(xyz(i32::MIN, 0, 0), xyz(-2, 1, 1), &air, vec![0]);
+    let error = read_rom(file.as_slice(), &layout, &Machine::default()).unwrap_err();
+    assert_eq!(error, "Region rom has an invalid position");
+    let huge = 1 << 30;
+    let file = litematic(xyz(0, 0, 0), xyz(huge, huge, huge), &air, vec![0]);
+    let error = read_rom(file.as_slice(), &layout, &Machine::default()).unwrap_err();
+    assert_eq!(error, "Region rom has the wrong number of blocks");
+}
+
+#[test]
+fn sponge_version_3_imports() {
+    let layout = RomLayout::from_toml("one = \"minecraft:redstone_torch\"").unwrap();
+    let program = ProgramLoader::assemble("JMP 5").unwrap();
+    let version_2 = schematic(&program, &layout).unwrap();
+    let Tag::Compound(mut tags) = version_2 else {
+        panic!("schematics are compounds");
+    };
+    // Version 3 moves the blocks into their own compound, and names the palette
+    // entries with their block states
+    let take = |tags: &mut Vec<(String, Tag)>, name: &str| {
+        let index = tags.iter().position(|(tag, _)| tag == name).unwrap();
+        tags.remove(index).1
+    };
+    let Tag::Compound(palette) = take(&mut tags, "Palette") else {
+        panic!("palettes are compounds");
+    };
+    let palette = palette
+        .into_iter()
+        .map(|(name, index)| match name.as_str() {
+            "minecraft:redstone_torch" => ("minecraft:redstone_torch[lit=true]".to_string(), index),
+            _ => (name, index),
+        })
+        .collect();
+    let data = take(&mut tags, "BlockData");
+    tags.push((
+        "Blocks".to_string(),
+        Tag::Compound(vec![
+            ("Palette".to_string(), Tag::Compound(palette)),
+            ("Data".to_string(), data),
+        ]),
+    ));
+    let root = Tag::Compound(vec![("Schematic".to_string(), Tag::Compound(tags))]);
+    let read = read_rom(gzip("", &root).as_slice(), &layout, &Machine::default()).unwrap();
+    assert_eq!(read, program.to_rom().unwrap());
+}
+
+#[test]
+fn truncated_sponge_schematics_are_errors() {
+    let layout = RomLayout::default();
+    let program = ProgramLoader::assemble("IMM R1 1").unwrap();
+    let Tag::Compound(mut tags) = schematic(&program, &layout).unwrap() else {
+        panic!("schematics are compounds");
+    };
+    for (name, tag) in &mut tags {
+        if let ("BlockData", Tag::ByteArray(data)) = (name.as_str(), tag) {
+            data.truncate(data.len() / 2);
+        }
+    }
+    let file = gzip("Schematic", &Tag::Compound(tags));
+    let error = read_rom(file.as_slice(), &layout, &Machine::default()).unwrap_err();
+    assert_eq!(error, "The schematic has the wrong number of blocks");
+}
+
+#[test]
+fn unexpected_blocks_are_errors() {
+    let layout = RomLayout::default();
+    let program = ProgramLoader::assemble("IMM R1 1").unwrap();
+    let mut file = vec![];
+    write_schematic(&program, &layout, &mut file).unwrap();
+    let other = RomLayout::from_toml("one = \"minecraft:stone\"").unwrap();
+    let error = read_rom(file.as_slice(), &other, &Machine::default()).unwrap_err();
+    assert!(error.contains("minecraft:redstone_torch"), "{error}");
+    assert!(read_rom(&b"not a schematic"[..], &layout, &Machine::default()).is_err());
+}
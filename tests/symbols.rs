@@ -0,0 +1,113 @@
+mod common;
+use common::*;
+
+#[test]
+fn labels_can_be_used_before_they_are_defined() {
+    let program = assemble("IMM R1 end\nJMP end\nNOOP\nIMM R1 1\nend:\n  OUT %0 R1\nJMP end");
+    assert_eq!(
+        code(&program.instructions),
+        [
+            "IMM R1 4",
+            "JMP 4",
+            "NOOP",
+            "IMM R1 1",
+            "OUT %0 R1",
+            "JMP 4"
+        ]
+    );
+    assert_eq!(run(program)[0], 4);
+}
+
+#[test]
+fn undefined_labels_are_errors() {
+    let errors = assembly_errors("JMP ennd\nend: NOOP");
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(errors[0].message, "\"ENND\" is not defined");
+    assert_eq!(
+        (errors[0].line, errors[0].column, errors[0].length),
+        (0, 4, 4)
+    );
+    assert_eq!(errors[0].help.as_deref(), Some("did you mean END?"));
+    let far_off = assembly_errors("JMP nowhere\nend: NOOP");
+    assert_eq!(far_off[0].message, "\"NOWHERE\" is not defined");
+    assert_eq!(far_off[0].help, None);
+}
+
+#[test]
+fn labels_can_only_be_defined_once() {
+    let errors = assembly_errors("loop: NOOP\nOUT %0 R1\nLoop: NOOP\nJMP loop");
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(errors[0].message, "\"LOOP\" is already defined on line 1");
+    assert_eq!(
+        (errors[0].line, errors[0].column, errors[0].length),
+        (2, 0, 4)
+    );
+}
+
+#[test]
+fn constants_can_be_used_in_oprands() {
+    let program =
+        assemble(".equ WIDTH 8\n.equ LAST WIDTH - 1\n.equ PORT 2\nIMM R(PORT) LAST\nOUT %PORT R2");
+    assert_eq!(code(&program.instructions), ["IMM R2 7", "OUT %2 R2"]);
+    let constants: Vec<(&str, &str)> = program
+        .symbols
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), symbol.value.as_str()))
+        .collect();
+    assert_eq!(constants, [("LAST", "7"), ("PORT", "2"), ("WIDTH", "8")]);
+    assert_eq!(run(program)[2], 7);
+}
+
+#[test]
+fn constants_need_valid_unused_names() {
+    let message = |source: &str| {
+        let errors = assembly_errors(source);
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        (errors[0].line, errors[0].column, errors[0].message.clone())
+    };
+    assert_eq!(
+        message(".equ R1 5\nIMM R1 1"),
+        (0, 5, "\"R1\" is not a valid constant name".to_string())
+    );
+    assert_eq!(
+        message(".equ 5 6"),
+        (0, 5, "\"5\" is not a valid constant name".to_string())
+    );
+    assert_eq!(
+        message(".equ SIZE 1\n.equ size 2"),
+        (1, 5, "\"SIZE\" is already defined on line 1".to_string())
+    );
+    assert_eq!(
+        message("loop: NOOP\n.equ LOOP 2"),
+        (1, 5, "\"LOOP\" is already defined on line 1".to_string())
+    );
+    // Constants are defined in order, unlike labels
+    assert_eq!(
+        message(".equ A B\n.equ B 1"),
+        (0, 7, "\"B\" is not defined".to_string())
+    );
+}
+
+#[test]
+fn aliases_apply_from_their_line() {
+    let program = assemble(
+        ".alias counter R1\nIMM counter 3\n.alias counter R2\nIMM counter 4\n.alias out %1\nOUT out R1",
+    );
+    assert_eq!(
+        code(&program.instructions),
+        ["IMM R1 3", "IMM R2 4", "OUT %1 R1"]
+    );
+    let errors = assembly_errors("IMM counter 1\n.alias counter R1\nOUT %0 counter");
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(errors[0].line, 0);
+    assert_eq!(errors[0].message, "\"COUNTER\" is not defined");
+    let errors = assembly_errors(".equ N 2\n.alias n R3\n.alias 5x R1");
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "\"N\" is already defined on line 1",
+            "\"5X\" is not a valid alias name"
+        ]
+    );
+}